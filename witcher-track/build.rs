@@ -1,29 +1,70 @@
-use std::path::PathBuf;
-use std::{env, fs};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+/// Local traineddata file bundled as the English model.
+const TRAINED_DATA_ENV: &str = "WITCHER_TRACK_TRAINEDDATA";
 
 fn out_dir() -> PathBuf {
     PathBuf::from(env::var("OUT_DIR").unwrap())
 }
 
+/// Copy the traineddata file given by `WITCHER_TRACK_TRAINEDDATA`.
+fn copy_trained_data(source: &Path, trained_data_path: &Path) -> Result<(), String> {
+    println!("cargo:rerun-if-changed={}", source.display());
+
+    fs::copy(source, trained_data_path)
+        .map(|_| ())
+        .map_err(|e| format!("Could not copy {TRAINED_DATA_ENV}={}: {e}", source.display()))
+}
+
 /// Download english Tesseract trained data if it is not present.
-pub fn download_trained_data() {
+#[cfg(feature = "download-traineddata")]
+fn download_trained_data(trained_data_path: &Path) -> Result<(), String> {
     const ENG_TRAINEDDATA_URL: &str =
         "https://github.com/tesseract-ocr/tessdata_best/raw/main/eng.traineddata";
 
-    let trained_data_path = out_dir().join("eng.traineddata");
-
     if trained_data_path.exists() {
-        return;
+        return Ok(());
     }
 
-    let response = ureq::get(ENG_TRAINEDDATA_URL).call().unwrap();
-    let mut bytes = Vec::with_capacity(response.header("Content-Length").unwrap().parse().unwrap());
-    response.into_reader().read_to_end(&mut bytes).unwrap();
-    fs::write(trained_data_path, bytes).unwrap();
+    let response = ureq::get(ENG_TRAINEDDATA_URL)
+        .call()
+        .map_err(|e| format!("Could not download {ENG_TRAINEDDATA_URL}: {e}"))?;
+    let length = response.header("Content-Length").and_then(|length| length.parse().ok());
+    let mut bytes = Vec::with_capacity(length.unwrap_or(0));
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Could not download {ENG_TRAINEDDATA_URL}: {e}"))?;
+    fs::write(trained_data_path, bytes)
+        .map_err(|e| format!("Could not write {}: {e}", trained_data_path.display()))
+}
+
+#[cfg(not(feature = "download-traineddata"))]
+fn download_trained_data(_: &Path) -> Result<(), String> {
+    Err(format!(
+        "No English traineddata to bundle. Set {TRAINED_DATA_ENV} to the path of a local \
+         eng.traineddata, or enable the download-traineddata feature to fetch it from GitHub."
+    ))
+}
+
+/// Bundle a local traineddata file if one is given, or else download it.
+fn trained_data() -> Result<(), String> {
+    println!("cargo:rerun-if-env-changed={TRAINED_DATA_ENV}");
+    let trained_data_path = out_dir().join("eng.traineddata");
+
+    match env::var_os(TRAINED_DATA_ENV) {
+        Some(source) => copy_trained_data(Path::new(&source), &trained_data_path),
+        None => download_trained_data(&trained_data_path),
+    }
 }
 
 fn main() {
-    download_trained_data();
+    if let Err(message) = trained_data() {
+        eprintln!("error: {message}");
+        process::exit(1);
+    }
+
     println!("cargo:rustc-link-lib=static=archive");
     if cfg!(target_os = "windows") {
         println!("cargo:rustc-link-lib=User32");
@@ -0,0 +1,155 @@
+//! Cache of OCR results keyed by the binarized picture and the characters
+//! Tesseract may recognize in it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::{fmt, slice};
+
+use anyhow::Result;
+use leptonica_sys::*;
+
+use crate::picture::Picture;
+
+/// Number of cache lookups that hit or missed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        if self.hits + self.misses == 0 {
+            0.
+        } else {
+            self.hits as f64 / (self.hits + self.misses) as f64
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate)",
+            self.hits,
+            self.misses,
+            100. * self.hit_rate()
+        )
+    }
+}
+
+/// Least recently used cache of OCR results.
+///
+/// The cache can be shared by workers reading frames concurrently. It is only
+/// locked to look results up and insert them, not while OCR runs.
+pub struct OcrCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+struct CacheState {
+    /// Most recently used last.
+    entries: VecDeque<(u64, String)>,
+    stats: CacheStats,
+}
+
+impl OcrCache {
+    /// Construct a new instance holding up to `capacity` results.
+    pub fn new(capacity: usize) -> Self {
+        let state =
+            CacheState { entries: VecDeque::with_capacity(capacity), stats: CacheStats::default() };
+        Self { capacity, state: Mutex::new(state) }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Return the cached text of a picture read with `whitelist`, or compute
+    /// and cache it with `ocr`.
+    pub fn get_or_try_insert(
+        &self,
+        bin_pic: &Picture,
+        whitelist: &str,
+        ocr: impl FnOnce() -> Result<String>,
+    ) -> Result<String> {
+        let key = hash(bin_pic, whitelist);
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(position) = state.entries.iter().position(|(k, _)| *k == key) {
+                state.stats.hits += 1;
+                let entry = state.entries.remove(position).unwrap();
+                let text = entry.1.clone();
+                state.entries.push_back(entry);
+                return Ok(text);
+            }
+            state.stats.misses += 1;
+        }
+
+        let text = ocr()?;
+        let mut state = self.state.lock().unwrap();
+        if state.entries.iter().any(|(k, _)| *k == key) {
+            return Ok(text);
+        }
+        if state.entries.len() >= self.capacity {
+            state.entries.pop_front();
+        }
+        if self.capacity > 0 {
+            state.entries.push_back((key, text.clone()));
+        }
+
+        Ok(text)
+    }
+}
+
+/// Hash the dimensions and pixel data of a picture, along with a whitelist.
+fn hash(pic: &Picture, whitelist: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    whitelist.hash(&mut hasher);
+    unsafe {
+        let (width, height) = (pixGetWidth(**pic), pixGetHeight(**pic));
+        let wpl = pixGetWpl(**pic) as usize;
+        (width, height, pixGetDepth(**pic)).hash(&mut hasher);
+        slice::from_raw_parts(pixGetData(**pic), wpl * height as usize).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr::null_mut;
+
+    use super::*;
+
+    fn picture(width: i32) -> Picture {
+        let pic = Picture::from(unsafe { pixCreate(width, 10, 1) });
+        unsafe { pixRasterop(*pic, 0, 0, 5, 5, PIX_SET as _, null_mut(), 0, 0) };
+        pic
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = OcrCache::new(2);
+        let ocr = |text: &str| {
+            let text = text.to_string();
+            move || Ok(text)
+        };
+
+        assert_eq!(cache.get_or_try_insert(&picture(10), "", ocr("a")).unwrap(), "a");
+        assert_eq!(cache.get_or_try_insert(&picture(20), "", ocr("b")).unwrap(), "b");
+        assert_eq!(cache.get_or_try_insert(&picture(10), "", ocr("x")).unwrap(), "a");
+        // Evicts the least recently used picture, of width 20.
+        assert_eq!(cache.get_or_try_insert(&picture(30), "", ocr("c")).unwrap(), "c");
+        assert_eq!(cache.get_or_try_insert(&picture(20), "", ocr("d")).unwrap(), "d");
+
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 4 });
+
+        // The same picture read with another whitelist is another entry.
+        assert_eq!(cache.get_or_try_insert(&picture(20), "abc", ocr("e")).unwrap(), "e");
+        assert_eq!(cache.get_or_try_insert(&picture(20), "", ocr("x")).unwrap(), "d");
+    }
+}
@@ -0,0 +1,117 @@
+//! Crop and color ranges tuned on a sample screenshot.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use leptonica_sys::pixXor;
+
+use crate::config::TrackerConfig;
+use crate::locate;
+use crate::picture::{close_gaps, color_mask, filter_components, Picture};
+
+/// Number of times every bound is swept by [`sweep`].
+const PASSES: usize = 2;
+
+/// Bound of the crop or color range adjusted by [`sweep`], with the values it
+/// tries.
+struct Knob {
+    values: Vec<f32>,
+    set: fn(&mut TrackerConfig, f32),
+}
+
+fn knobs() -> Vec<Knob> {
+    let steps = |start: f32, end: f32, step: f32| {
+        (0..=((end - start) / step).round() as usize).map(|i| start + i as f32 * step).collect()
+    };
+
+    vec![
+        Knob { values: steps(0.3, 0.5, 0.05), set: |c, v| c.crop.top = v },
+        Knob { values: steps(0.2, 0.45, 0.05), set: |c, v| c.crop.bottom = v },
+        Knob { values: steps(40., 100., 10.), set: |c, v| c.hsv.hue.end = v as u8 },
+        Knob { values: steps(0., 40., 10.), set: |c, v| c.hsv.saturation.start = v as u8 },
+        Knob { values: steps(80., 180., 20.), set: |c, v| c.hsv.saturation.end = v as u8 },
+        Knob { values: steps(100., 200., 10.), set: |c, v| c.hsv.value.start = v as u8 },
+    ]
+}
+
+/// Adjust one bound at a time, keeping the value with the highest score.
+///
+/// Ties keep the current value. Returns the best config and its score.
+pub fn sweep(
+    config: TrackerConfig,
+    mut score: impl FnMut(&TrackerConfig) -> Result<f64>,
+) -> Result<(TrackerConfig, f64)> {
+    let mut best_score = score(&config)?;
+    let mut best = config;
+
+    for _ in 0..PASSES {
+        for knob in knobs() {
+            for &value in &knob.values {
+                let mut candidate = best.clone();
+                (knob.set)(&mut candidate, value);
+                if candidate == best {
+                    continue;
+                }
+
+                let candidate_score = score(&candidate)?;
+                if candidate_score > best_score {
+                    best = candidate;
+                    best_score = candidate_score;
+                }
+            }
+        }
+    }
+
+    Ok((best, best_score))
+}
+
+/// Write a picture of each preprocessing stage of a screenshot to `dir`.
+pub fn write_stages<P: AsRef<Path>>(
+    screenshot: &Picture,
+    config: &TrackerConfig,
+    dir: P,
+) -> Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let cropped = screenshot.clone().into_cropped(&config.crop, config.hud_scale)?;
+    cropped.save_png(dir.join("1-crop.png"))?;
+
+    let mask = unsafe { color_mask(&cropped, &config.hsv)? };
+    mask.save_png(dir.join("2-mask.png"))?;
+
+    let mask = locate::clip(&mask, locate::box_region(&mask, &config.locate))?;
+    mask.save_png(dir.join("3-box.png"))?;
+
+    unsafe { close_gaps(&mask) };
+    mask.save_png(dir.join("4-morphology.png"))?;
+
+    let kept = mask.clone();
+    unsafe { filter_components(&kept, &config.components) };
+    kept.save_png(dir.join("5-components-kept.png"))?;
+
+    let removed = Picture::from(unsafe { pixXor(std::ptr::null_mut(), *mask, *kept) });
+    removed.save_png(dir.join("6-components-removed.png"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CROP_RANGE;
+
+    #[test]
+    fn test_sweep() {
+        // Peaks at a hue end of 60 and a value start of 170.
+        let score = |c: &TrackerConfig| {
+            Ok(-(c.hsv.hue.end as f64 - 60.).abs() - (c.hsv.value.start as f64 - 170.).abs())
+        };
+
+        let (best, best_score) = sweep(TrackerConfig::default(), score).unwrap();
+        assert_eq!((best.hsv.hue.end, best.hsv.value.start), (60, 170));
+        assert_eq!(best_score, 0.);
+        assert_eq!(best.crop, CROP_RANGE);
+    }
+}
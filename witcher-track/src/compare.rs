@@ -0,0 +1,120 @@
+//! Comparison of OCR models on the fixtures and their expected actions.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::TrackerConfig;
+use crate::data::{parse_action_with, slugify, Action};
+use crate::picture::Picture;
+use crate::{pipeline, OcrReader};
+
+/// Screenshot and the action expected to be parsed from it, if any.
+#[derive(Debug, PartialEq)]
+pub struct Fixture {
+    pub path: String,
+    pub expected: Option<Action>,
+    /// Reported, but not expected to be parsed correctly.
+    pub known_failure: bool,
+}
+
+/// Parse a ground truth file.
+///
+/// Each line reads `<fixture> [<quest|formula|diagram> <name>]`, prefixed with
+/// `!` for known failures. Empty lines and lines starting with `#` are
+/// ignored.
+pub fn parse_fixtures(s: &str) -> Result<Vec<Fixture>> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(idx, line)| {
+            parse_fixture(line).with_context(|| format!("Invalid fixture on line {}", idx + 1))
+        })
+        .collect()
+}
+
+/// Load and parse a ground truth file.
+pub fn load_fixtures<P: AsRef<Path>>(path: P) -> Result<Vec<Fixture>> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Could not read fixtures {path:?}"))?;
+    parse_fixtures(&content).with_context(|| format!("Invalid fixtures {path:?}"))
+}
+
+fn parse_fixture(line: &str) -> Result<Fixture> {
+    let (known_failure, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let mut fields = line.trim().splitn(3, ' ');
+    let path = fields.next().unwrap().to_string();
+    let expected = match fields.next() {
+        Some(action_type) => {
+            let name = fields.next().ok_or_else(|| anyhow!("Missing name in {line:?}"))?;
+            Some(Action::new(action_type.parse()?, slugify(name)))
+        },
+        None => None,
+    };
+
+    Ok(Fixture { path, expected, known_failure })
+}
+
+/// Action a model read on a fixture.
+#[derive(Debug)]
+pub struct Reading {
+    pub action: Option<Action>,
+    pub elapsed: Duration,
+}
+
+impl Reading {
+    pub fn is_correct(&self, fixture: &Fixture) -> bool {
+        self.action == fixture.expected
+    }
+}
+
+/// Run the pipeline on a fixture and parse its text.
+pub fn read_fixture(
+    ocr_reader: &OcrReader,
+    config: &TrackerConfig,
+    fixture: &Fixture,
+) -> Result<Reading> {
+    let data = fs::read(&fixture.path)
+        .with_context(|| format!("Could not read fixture {:?}", fixture.path))?;
+    let start = Instant::now();
+
+    let (text, _) = pipeline::read_frame(ocr_reader, config, Picture::from_mem(data))?;
+    let action = parse_action_with(text, &config.thresholds, config.language()?);
+
+    Ok(Reading { action, elapsed: start.elapsed() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ActionType;
+
+    #[test]
+    fn test_parse_fixtures() {
+        let fixtures = parse_fixtures(
+            "# Comment\n\ntests/a.png quest On Deaths Bed\ntests/b.png\n!tests/c.png diagram \
+             Diagram: Broadhead bolt\n",
+        )
+        .unwrap();
+        assert_eq!(fixtures.len(), 3);
+        assert_eq!(
+            fixtures[0].expected,
+            Some(Action::new(ActionType::Quest, slugify("On Deaths Bed")))
+        );
+        assert_eq!(fixtures[1], Fixture {
+            path: "tests/b.png".to_string(),
+            expected: None,
+            known_failure: false
+        });
+        assert!(fixtures[2].known_failure);
+
+        let error = parse_fixtures("tests/a.png quest\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid fixture on line 1");
+    }
+}
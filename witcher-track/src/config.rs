@@ -0,0 +1,339 @@
+//! Tuning values loaded from a TOML file at startup.
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{ActionType, Language, Thresholds};
+use crate::debounce::DebouncePolicy;
+use crate::gate::GatePolicy;
+use crate::locate::LocatePolicy;
+use crate::ocr::{Model, OcrSettings};
+use crate::{
+    Crop, HsvRange, CAPTURE_INTERVAL_MS, COMPONENT_AREA, COMPONENT_ASPECT_RATIO, CROP_RANGE,
+    DEBOUNCE_POLICY, FRAME_QUEUE_SIZE, GATE_POLICY, HSV_RANGE, HUD_SCALE, LANGUAGE, LOCATE_POLICY,
+    OCR_CACHE_SIZE, OCR_WORKERS, THRESHOLDS,
+};
+
+/// Connected components of the mask that are kept as glyphs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentFilter {
+    /// Bounding box area, in pixels of the scaled crop.
+    pub area: Range<i32>,
+    /// Bounding box width over height.
+    pub aspect_ratio: Range<f32>,
+}
+
+/// Part of the notification box read on its own, like the header or the item
+/// name, which differ in font and color.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextField {
+    pub name: String,
+    /// Band of the notification box holding the field, as fractions of its
+    /// height.
+    pub rows: Range<f32>,
+    pub hsv: HsvRange,
+    /// Characters Tesseract may recognize in the field. If empty, the
+    /// whitelist of the OCR settings applies.
+    #[serde(default)]
+    pub whitelist: String,
+}
+
+/// Every value the tracker can be tuned with. Missing keys keep their default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackerConfig {
+    /// Tesseract code of the language the game runs in, which selects the
+    /// notification headers, the item names and, unless `ocr.model` is set,
+    /// the Tesseract model.
+    pub language: String,
+    /// In-game HUD scale, which the notification box grows with.
+    pub hud_scale: f32,
+    /// Region searched for the notification box, at HUD scale 1.
+    pub crop: Crop,
+    pub hsv: HsvRange,
+    pub locate: LocatePolicy,
+    /// Fields read separately, in the order the parser expects them: header
+    /// first, then name. If empty, the whole box is read with `hsv`.
+    pub fields: Vec<TextField>,
+    pub ocr: OcrSettings,
+    pub thresholds: Thresholds,
+    pub components: ComponentFilter,
+    /// Frames that must agree on an action before it is flagged.
+    pub debounce: DebouncePolicy,
+    /// Frames skipped before OCR when they hold no text or didn't change.
+    pub gate: GatePolicy,
+    /// OCR results kept for frames read again. 0 disables the cache.
+    pub ocr_cache_size: usize,
+    /// Threads reading live frames.
+    pub workers: usize,
+    /// Live frames waiting for a worker, beyond which the oldest is dropped.
+    pub frame_queue_size: usize,
+    /// Delay between two live captures.
+    pub capture_interval_ms: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            language: LANGUAGE.to_string(),
+            hud_scale: HUD_SCALE,
+            crop: CROP_RANGE,
+            hsv: HSV_RANGE,
+            locate: LOCATE_POLICY,
+            fields: Vec::new(),
+            ocr: OcrSettings::default(),
+            thresholds: THRESHOLDS,
+            components: ComponentFilter {
+                area: COMPONENT_AREA,
+                aspect_ratio: COMPONENT_ASPECT_RATIO,
+            },
+            debounce: DEBOUNCE_POLICY,
+            gate: GATE_POLICY,
+            ocr_cache_size: OCR_CACHE_SIZE,
+            workers: OCR_WORKERS,
+            frame_queue_size: FRAME_QUEUE_SIZE,
+            capture_interval_ms: CAPTURE_INTERVAL_MS,
+        }
+    }
+}
+
+impl TrackerConfig {
+    /// Load and validate a config file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Could not read config {path:?}"))?;
+        let config: Self =
+            toml::from_str(&content).with_context(|| format!("Invalid config {path:?}"))?;
+        config.validate().with_context(|| format!("Invalid config {path:?}"))?;
+
+        Ok(config)
+    }
+
+    /// Load a config file, falling back to the defaults if there is none.
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if path.as_ref().exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(fs::write(path, toml::to_string(self)?)?)
+    }
+
+    pub fn capture_interval(&self) -> Duration {
+        Duration::from_millis(self.capture_interval_ms)
+    }
+
+    /// Headers and item names of the configured language.
+    pub fn language(&self) -> Result<&'static Language> {
+        Language::get(&self.language)
+    }
+
+    /// OCR settings, with the Tesseract model of the configured language
+    /// unless a model is set.
+    pub fn ocr_settings(&self) -> OcrSettings {
+        let model = self.ocr.model.clone().unwrap_or_else(|| Model::from(self.language.as_str()));
+        OcrSettings { model: Some(model), ..self.ocr.clone() }
+    }
+
+    /// Check that every value is within its range.
+    pub fn validate(&self) -> Result<()> {
+        self.language()?;
+
+        let hud_scale = self.hud_scale;
+        ensure!(hud_scale > 0., "hud_scale must be positive, got {hud_scale}");
+
+        let Crop { left, right, top, bottom } = self.crop;
+        for (name, value) in [("left", left), ("right", right), ("top", top), ("bottom", bottom)] {
+            ensure!((0. ..1.).contains(&value), "crop.{name} must be in [0, 1), got {value}");
+        }
+        ensure!(left + right < 1., "crop.left + crop.right must be below 1");
+        ensure!(top + bottom < 1., "crop.top + crop.bottom must be below 1");
+
+        validate_hsv("hsv", &self.hsv)?;
+
+        let LocatePolicy { merge_width, merge_height, padding, .. } = self.locate;
+        ensure!(merge_width > 0, "locate.merge_width must be positive, got {merge_width}");
+        ensure!(merge_height > 0, "locate.merge_height must be positive, got {merge_height}");
+        ensure!(padding >= 0, "locate.padding must not be negative, got {padding}");
+
+        for (i, field) in self.fields.iter().enumerate() {
+            let TextField { name, rows, hsv, .. } = field;
+            ensure!(!name.is_empty(), "fields[{i}].name must not be empty");
+            ensure!(
+                self.fields[..i].iter().all(|other| other.name != *name),
+                "fields[{i}].name {name:?} is used twice"
+            );
+            ensure!(
+                0. <= rows.start && rows.start < rows.end && rows.end <= 1.,
+                "fields.{name}.rows must be a non-empty range within 0..1, got {rows:?}"
+            );
+            validate_hsv(&format!("fields.{name}.hsv"), hsv)?;
+        }
+
+        if let Some(dpi) = self.ocr.dpi {
+            ensure!(dpi > 0, "ocr.dpi must be positive");
+        }
+        if let Some(min_confidence) = self.ocr.min_confidence {
+            ensure!(
+                (0. ..=100.).contains(&min_confidence),
+                "ocr.min_confidence must be in [0, 100], got {min_confidence}"
+            );
+        }
+        ensure!(
+            self.ocr.variables.keys().all(|name| !name.is_empty()),
+            "ocr.variables must not have an empty name"
+        );
+
+        for action_type in ActionType::ALL {
+            let threshold = self.thresholds.get(action_type);
+            let name = action_type.as_str();
+            ensure!(
+                (0. ..=1.).contains(&threshold.header),
+                "thresholds.{name}.header must be in [0, 1], got {}",
+                threshold.header
+            );
+            ensure!(
+                (0. ..=1.).contains(&threshold.name),
+                "thresholds.{name}.name must be in [0, 1], got {}",
+                threshold.name
+            );
+        }
+
+        let ComponentFilter { area, aspect_ratio } = &self.components;
+        ensure!(!area.is_empty(), "components.area must not be empty, got {area:?}");
+        ensure!(
+            !aspect_ratio.is_empty(),
+            "components.aspect_ratio must not be empty, got {aspect_ratio:?}"
+        );
+
+        let DebouncePolicy { min_detections, window } = self.debounce;
+        ensure!(min_detections > 0, "debounce.min_detections must be positive");
+        ensure!(
+            window >= min_detections,
+            "debounce.window must be at least debounce.min_detections, got {window}"
+        );
+
+        let GatePolicy { min_text_fraction, min_change_fraction } = self.gate;
+        ensure!(
+            (0. ..=1.).contains(&min_text_fraction),
+            "gate.min_text_fraction must be in [0, 1], got {min_text_fraction}"
+        );
+        ensure!(
+            (0. ..=1.).contains(&min_change_fraction),
+            "gate.min_change_fraction must be in [0, 1], got {min_change_fraction}"
+        );
+
+        ensure!(self.workers > 0, "workers must be positive");
+        ensure!(self.frame_queue_size > 0, "frame_queue_size must be positive");
+        ensure!(self.capture_interval_ms > 0, "capture_interval_ms must be positive");
+
+        Ok(())
+    }
+}
+
+fn validate_hsv(key: &str, hsv: &HsvRange) -> Result<()> {
+    let HsvRange { hue, saturation, value } = hsv;
+    for (name, range) in [("hue", hue), ("saturation", saturation), ("value", value)] {
+        ensure!(!range.is_empty(), "{key}.{name} must not be empty, got {range:?}");
+    }
+    ensure!(hue.end <= 240, "{key}.hue must be within 0..240, got {hue:?}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml() {
+        let config = TrackerConfig::default();
+        config.validate().unwrap();
+        let content = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<TrackerConfig>(&content).unwrap(), config);
+
+        let config: TrackerConfig = toml::from_str("capture_interval_ms = 250").unwrap();
+        assert_eq!(config.capture_interval(), Duration::from_millis(250));
+        assert_eq!(config.hsv, HSV_RANGE);
+
+        assert!(toml::from_str::<TrackerConfig>("capture_interval = 250").is_err());
+    }
+
+    #[test]
+    fn test_language() {
+        let config: TrackerConfig = toml::from_str("language = \"pol\"").unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.ocr_settings().model, Some(Model::Language("pol".to_string())));
+
+        let config = TrackerConfig {
+            ocr: OcrSettings { model: Some(Model::Witcher), ..Default::default() },
+            ..config
+        };
+        assert_eq!(config.ocr_settings().model, Some(Model::Witcher));
+        assert_eq!(TrackerConfig::default().ocr_settings().model, Some(Model::Eng));
+
+        let config = TrackerConfig { language: "klingon".to_string(), ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = TrackerConfig::default();
+        config.crop.left = 0.6;
+        let error = config.validate().unwrap_err();
+        assert_eq!(error.to_string(), "crop.left + crop.right must be below 1");
+
+        let mut config = TrackerConfig::default();
+        config.thresholds.diagram.name = 1.5;
+        let error = config.validate().unwrap_err();
+        assert_eq!(error.to_string(), "thresholds.diagram.name must be in [0, 1], got 1.5");
+
+        let mut config = TrackerConfig::default();
+        config.hsv.value.start = 255;
+        assert!(config.validate().is_err());
+
+        let mut config = TrackerConfig::default();
+        config.debounce.window = 1;
+        assert!(config.validate().is_err());
+
+        let config = TrackerConfig { hud_scale: 0., ..Default::default() };
+        let error = config.validate().unwrap_err();
+        assert_eq!(error.to_string(), "hud_scale must be positive, got 0");
+
+        let mut config = TrackerConfig::default();
+        config.gate.min_change_fraction = -0.1;
+        let error = config.validate().unwrap_err();
+        assert_eq!(error.to_string(), "gate.min_change_fraction must be in [0, 1], got -0.1");
+
+        let config = TrackerConfig { workers: 0, ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = TrackerConfig { ocr_cache_size: 0, ..Default::default() };
+        config.validate().unwrap();
+
+        let field = |name: &str, rows| TextField {
+            name: name.to_string(),
+            rows,
+            hsv: HSV_RANGE,
+            whitelist: String::new(),
+        };
+        let mut config = TrackerConfig {
+            fields: vec![field("header", 0.0..0.4), field("name", 0.4..1.0)],
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        config.fields.push(field("name", 0.5..1.0));
+        let error = config.validate().unwrap_err();
+        assert_eq!(error.to_string(), "fields[2].name \"name\" is used twice");
+        config.fields[2] = field("footer", 0.5..1.5);
+        assert!(config.validate().is_err());
+    }
+}
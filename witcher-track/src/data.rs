@@ -1,85 +1,358 @@
-use std::collections::HashSet;
-use std::str::Lines;
+use std::collections::{HashMap, HashSet};
+use std::str::{FromStr, Lines};
 
+use anyhow::{anyhow, Context, Error, Result};
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use strsim::normalized_damerau_levenshtein;
 
-use crate::STRSIM_THRESHOLD;
+use crate::THRESHOLDS;
+
+const DIAGRAMS_TXT: &str = include_str!("../data/tw3diagramlist.txt");
+const FORMULAE_TXT: &str = include_str!("../data/tw3formulaelist.txt");
+const QUESTS_TXT: &str = include_str!("../data/tw3questlist.txt");
+/// Translations bundled with the tracker, by Tesseract language code.
+const TRANSLATIONS: &[(&str, &str)] = &[
+    ("deu", include_str!("../data/lang/deu.toml")),
+    ("ita", include_str!("../data/lang/ita.toml")),
+    ("pol", include_str!("../data/lang/pol.toml")),
+];
 
 lazy_static! {
-    pub static ref DIAGRAMS: HashSet<String> =
-        include_str!("../data/tw3diagramlist.txt").trim().lines().map(slugify).collect();
-    pub static ref FORMULAE: HashSet<String> =
-        include_str!("../data/tw3formulaelist.txt").trim().lines().map(slugify).collect();
-    pub static ref QUESTS: HashSet<String> =
-        include_str!("../data/tw3questlist.txt").trim().lines().map(slugify).collect();
+    pub static ref DIAGRAMS: HashSet<String> = DIAGRAMS_TXT.trim().lines().map(slugify).collect();
+    pub static ref FORMULAE: HashSet<String> = FORMULAE_TXT.trim().lines().map(slugify).collect();
+    pub static ref QUESTS: HashSet<String> = QUESTS_TXT.trim().lines().map(slugify).collect();
     pub static ref DEFAULT_DIAGRAMS: HashSet<String> =
         include_str!("../data/tw3defaultdiagramlist.txt").trim().lines().map(slugify).collect();
     pub static ref DEFAULT_FORMULAE: HashSet<String> =
         include_str!("../data/tw3defaultformulaelist.txt").trim().lines().map(slugify).collect();
+    static ref ENGLISH: Language = Language::untranslated();
+    static ref LANGUAGES: Vec<Result<Language>> = TRANSLATIONS
+        .iter()
+        .map(|(code, content)| {
+            Language::translated(code, content)
+                .with_context(|| format!("Invalid translation data/lang/{code}.toml"))
+        })
+        .collect();
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Action {
     Quest(String),
     Formula(String),
     Diagram(String),
 }
 
-enum ActionType {
+impl Action {
+    pub fn new(action_type: ActionType, name: String) -> Self {
+        match action_type {
+            ActionType::Quest => Action::Quest(name),
+            ActionType::Formula => Action::Formula(name),
+            ActionType::Diagram => Action::Diagram(name),
+        }
+    }
+
+    pub fn action_type(&self) -> ActionType {
+        match self {
+            Action::Quest(_) => ActionType::Quest,
+            Action::Formula(_) => ActionType::Formula,
+            Action::Diagram(_) => ActionType::Diagram,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionType {
     Quest,
     Formula,
     Diagram,
 }
 
-fn check_str(a: &str, b: &str) -> bool {
-    normalized_damerau_levenshtein(a, b) > 0.6
+impl ActionType {
+    /// All action types, in the order headers are checked.
+    pub const ALL: [ActionType; 3] = [ActionType::Quest, ActionType::Formula, ActionType::Diagram];
+
+    /// Notification header announcing this type of action.
+    pub fn header(self) -> &'static str {
+        match self {
+            ActionType::Quest => "quest completed",
+            ActionType::Formula => "new alchemy formula",
+            ActionType::Diagram => "new crafting diagram",
+        }
+    }
+
+    /// Catalog of item names for this type of action.
+    fn catalog(self) -> &'static HashSet<String> {
+        match self {
+            ActionType::Quest => &QUESTS,
+            ActionType::Formula => &FORMULAE,
+            ActionType::Diagram => &DIAGRAMS,
+        }
+    }
+
+    /// Catalog item names as shown in game, before slugifying.
+    pub fn catalog_entries(self) -> impl Iterator<Item = &'static str> {
+        let catalog = match self {
+            ActionType::Quest => QUESTS_TXT,
+            ActionType::Formula => FORMULAE_TXT,
+            ActionType::Diagram => DIAGRAMS_TXT,
+        };
+        catalog.trim().lines().map(str::trim).filter(|line| !line.is_empty())
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ActionType::Quest => "quest",
+            ActionType::Formula => "formula",
+            ActionType::Diagram => "diagram",
+        }
+    }
+}
+
+impl FromStr for ActionType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ActionType::ALL
+            .into_iter()
+            .find(|action_type| action_type.as_str() == s)
+            .ok_or_else(|| anyhow!("Unknown action type {s:?}"))
+    }
+}
+
+/// Notification headers and item names in one translation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Translation {
+    headers: Headers,
+    /// Translated names, keyed by the id of their English name.
+    #[serde(default)]
+    quests: HashMap<String, String>,
+    #[serde(default)]
+    formulae: HashMap<String, String>,
+    #[serde(default)]
+    diagrams: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Headers {
+    quest: String,
+    formula: String,
+    diagram: String,
+}
+
+/// Language the game runs in, with the headers and item names matched
+/// against OCR text.
+///
+/// Items are identified by the slug of their English name whatever the
+/// language, so that progress is shared across languages.
+#[derive(Debug)]
+pub struct Language {
+    pub code: &'static str,
+    /// Slugified header of each action type.
+    headers: [String; ActionType::ALL.len()],
+    /// Slugified item names of each action type, mapped to their id.
+    names: [HashMap<String, String>; ActionType::ALL.len()],
+}
+
+impl Language {
+    /// Language of a Tesseract language code, e.g. `deu`.
+    pub fn get(code: &str) -> Result<&'static Language> {
+        if code == ENGLISH.code {
+            return Ok(&ENGLISH);
+        }
+        let idx = TRANSLATIONS.iter().position(|(other, _)| *other == code).ok_or_else(|| {
+            anyhow!("Unsupported language {code:?}, expected one of {:?}", Self::codes())
+        })?;
+        LANGUAGES[idx].as_ref().map_err(|e| anyhow!("{e:#}"))
+    }
+
+    /// Language the catalogs are written in.
+    pub fn english() -> &'static Language {
+        &ENGLISH
+    }
+
+    /// Codes of the supported languages.
+    pub fn codes() -> Vec<&'static str> {
+        [ENGLISH.code].into_iter().chain(TRANSLATIONS.iter().map(|(code, _)| *code)).collect()
+    }
+
+    fn untranslated() -> Language {
+        let headers = ActionType::ALL.map(|action_type| action_type.header().to_string());
+        let names = ActionType::ALL.map(|action_type| {
+            action_type.catalog().iter().map(|id| (id.clone(), id.clone())).collect()
+        });
+        Language { code: "eng", headers, names }
+    }
+
+    /// Parse a translation. Names it lacks keep matching their English name.
+    fn translated(code: &'static str, content: &str) -> Result<Language> {
+        let Translation { headers, quests, formulae, diagrams } = toml::from_str(content)?;
+        let mut language = Language::untranslated();
+        language.code = code;
+        language.headers = [headers.quest, headers.formula, headers.diagram].map(slugify);
+
+        for (action_type, names) in ActionType::ALL.into_iter().zip([quests, formulae, diagrams]) {
+            for (id, name) in names {
+                if !action_type.catalog().contains(&id) {
+                    return Err(anyhow!("Unknown {} id {id:?}", action_type.as_str()));
+                }
+                language.names[action_type as usize].insert(slugify(name), id);
+            }
+        }
+
+        Ok(language)
+    }
+
+    /// Slugified notification header announcing this type of action.
+    pub fn header(&self, action_type: ActionType) -> &str {
+        &self.headers[action_type as usize]
+    }
+
+    /// Slugified item names of this type of action, mapped to their id.
+    fn names(&self, action_type: ActionType) -> &HashMap<String, String> {
+        &self.names[action_type as usize]
+    }
+}
+
+/// Similarity thresholds for matching one type of notification.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+    /// A line is the header if its similarity to it is above this.
+    pub header: f64,
+    /// An item name matches a catalog entry if its similarity is at least this.
+    pub name: f64,
+}
+
+/// Similarity thresholds for every type of notification.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub quest: Threshold,
+    pub formula: Threshold,
+    pub diagram: Threshold,
+}
+
+impl Thresholds {
+    pub fn get(&self, action_type: ActionType) -> &Threshold {
+        match action_type {
+            ActionType::Quest => &self.quest,
+            ActionType::Formula => &self.formula,
+            ActionType::Diagram => &self.diagram,
+        }
+    }
+
+    pub fn get_mut(&mut self, action_type: ActionType) -> &mut Threshold {
+        match action_type {
+            ActionType::Quest => &mut self.quest,
+            ActionType::Formula => &mut self.formula,
+            ActionType::Diagram => &mut self.diagram,
+        }
+    }
+
+    /// Override a single threshold from a setting like `diagram.name=0.8`.
+    pub fn set(&mut self, setting: &str) -> Result<()> {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=value, got {setting:?}"))?;
+        let (action_type, field) =
+            key.split_once('.').ok_or_else(|| anyhow!("Expected type.field, got {key:?}"))?;
+        let value: f64 = value.parse()?;
+
+        let threshold = self.get_mut(action_type.parse()?);
+        match field {
+            "header" => threshold.header = value,
+            "name" => threshold.name = value,
+            _ => return Err(anyhow!("Unknown threshold {field:?}, expected header or name")),
+        }
+
+        Ok(())
+    }
 }
 
-fn find_action(it: &mut Lines) -> Option<ActionType> {
+fn find_action(it: &mut Lines, thresholds: &Thresholds, language: &Language) -> Option<ActionType> {
     it.find_map(|line| {
         let line = slugify(line);
 
-        if check_str(&line, "quest completed") {
-            Some(ActionType::Quest)
-        } else if check_str(&line, "new alchemy formula") {
-            Some(ActionType::Formula)
-        } else if check_str(&line, "new crafting diagram") {
-            Some(ActionType::Diagram)
-        } else {
-            None
-        }
+        ActionType::ALL.into_iter().find(|action_type| {
+            normalized_damerau_levenshtein(&line, language.header(*action_type))
+                > thresholds.get(*action_type).header
+        })
     })
 }
 
+/// Maximum number of lines an item name can wrap onto in the notification box.
+const MAX_NAME_LINES: usize = 3;
+
 pub fn parse_action<S: AsRef<str>>(s: S) -> Option<Action> {
+    parse_action_with(s, &THRESHOLDS, Language::english())
+}
+
+/// Parse only the type of action announced by the notification header.
+pub fn parse_header<S: AsRef<str>>(
+    s: S,
+    thresholds: &Thresholds,
+    language: &Language,
+) -> Option<ActionType> {
+    find_action(&mut s.as_ref().trim().lines(), thresholds, language)
+}
+
+/// Parse an action using custom similarity thresholds, in the language the game
+/// runs in.
+pub fn parse_action_with<S: AsRef<str>>(
+    s: S,
+    thresholds: &Thresholds,
+    language: &Language,
+) -> Option<Action> {
+    parse_action_scored(s, thresholds, language).map(|(action, _)| action)
+}
+
+/// Parse an action, also returning the similarity score of its name. The
+/// action holds the id of the item, whatever the language.
+pub fn parse_action_scored<S: AsRef<str>>(
+    s: S,
+    thresholds: &Thresholds,
+    language: &Language,
+) -> Option<(Action, f64)> {
     let mut lines = s.as_ref().trim().lines();
-    let action = find_action(&mut lines)?;
-    let target = slugify(lines.next()?);
+    let action_type = find_action(&mut lines, thresholds, language)?;
+    let threshold = thresholds.get(action_type).name;
+    let names = language.names(action_type);
 
-    match action {
-        ActionType::Quest => get_closest_match(&target, &*QUESTS).map(Action::Quest),
-        ActionType::Formula => get_closest_match(&target, &*FORMULAE).map(Action::Formula),
-        ActionType::Diagram => get_closest_match(&target, &*DIAGRAMS).map(Action::Diagram),
-    }
+    let name_lines: Vec<String> =
+        lines.map(slugify).filter(|line| !line.is_empty()).take(MAX_NAME_LINES).collect();
+
+    // Long names wrap onto the following lines: try every prefix of the lines
+    // after the header and keep the one that best matches the catalog. Ties go
+    // to the longer prefix, as a wrapped name can start with a shorter entry
+    // (e.g. "Scavenger Hunt: Griffin School Gear").
+    let (target, score) = (1..=name_lines.len())
+        .filter_map(|count| {
+            get_closest_match(&name_lines[..count].join(" "), names.keys(), threshold)
+        })
+        .reduce(|best, candidate| if candidate.1 >= best.1 { candidate } else { best })?;
+
+    Some((Action::new(action_type, names[&target].clone()), score))
 }
 
+/// Lowercase alphanumeric words of `s`, separated by single spaces. Letters
+/// outside of ASCII are kept, so that translated names keep their accents.
 pub fn slugify<S: Into<String>>(s: S) -> String {
-    s.into()
-        .chars()
-        .filter_map(|char| match char {
-            char if char.is_ascii_alphanumeric() => Some(char.to_ascii_lowercase()),
-            char if char.is_whitespace() => Some(' '),
-            char if char.is_ascii_punctuation() => Some(' '),
-            _ => None,
-        })
-        .collect::<String>()
-        .split_whitespace()
-        .intersperse(" ")
-        .collect::<String>()
+    let mut slug = String::new();
+    for char in s.into().chars() {
+        match char {
+            char if char.is_alphanumeric() => slug.extend(char.to_lowercase()),
+            char if char.is_whitespace() => slug.push(' '),
+            char if char.is_ascii_punctuation() => slug.push(' '),
+            _ => (),
+        }
+    }
+
+    slug.split_whitespace().intersperse(" ").collect::<String>()
 }
 
-fn get_closest_match<'a, I>(word: &str, possibilities: I) -> Option<String>
+/// Return the closest catalog entry to `word` along with its similarity score.
+fn get_closest_match<'a, I>(word: &str, possibilities: I, threshold: f64) -> Option<(String, f64)>
 where
     I: IntoIterator<Item = &'a String>,
 {
@@ -87,7 +360,7 @@ where
         .into_iter()
         .filter_map(|possibility| {
             let score = normalized_damerau_levenshtein(word, possibility);
-            if score >= STRSIM_THRESHOLD {
+            if score >= threshold {
                 Some((possibility, score))
             } else {
                 None
@@ -97,7 +370,10 @@ where
 
     matches_with_scores.sort_by(|(_, score1), (_, score2)| score2.partial_cmp(score1).unwrap());
 
-    matches_with_scores.into_iter().map(|(matched_word, _)| matched_word.to_string()).next()
+    matches_with_scores
+        .into_iter()
+        .map(|(matched_word, score)| (matched_word.to_string(), score))
+        .next()
 }
 
 #[cfg(test)]
@@ -212,6 +488,86 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_wrapped_names() {
+        assert_eq!(
+            parse_action("new alchemy formula\ntornout page earth\nelemental decoction"),
+            Some(Action::Formula("torn out page earth elemental decoction".to_string()))
+        );
+        assert_eq!(
+            parse_action(
+                "gquest completed\n\nscavenger hunt griffin school gear\nupgrade diagrams part 2"
+            ),
+            Some(Action::Quest(
+                "scavenger hunt griffin school gear upgrade diagrams part 2".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_action("lquest completed\ntwisted firestarter\ns"),
+            Some(Action::Quest("twisted firestarter".to_string()))
+        );
+        assert_eq!(
+            parse_action("quest completed\nprecious cargo\nir nt\nt acpreciouscargd r i"),
+            Some(Action::Quest("precious cargo".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut thresholds = THRESHOLDS;
+        thresholds.set("diagram.name=0.85").unwrap();
+        thresholds.set("quest.header=0.5").unwrap();
+        assert_eq!(thresholds.diagram, Threshold { header: THRESHOLDS.diagram.header, name: 0.85 });
+        assert_eq!(thresholds.quest, Threshold { header: 0.5, name: THRESHOLDS.quest.name });
+
+        assert!(thresholds.set("diagram.name").is_err());
+        assert!(thresholds.set("book.name=0.5").is_err());
+        assert!(thresholds.set("quest.body=0.5").is_err());
+
+        thresholds.set("quest.name=1.0").unwrap();
+        assert_eq!(
+            parse_action_with("quest completed\nprecious cargd", &thresholds, Language::english()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_languages() {
+        for code in Language::codes() {
+            assert_eq!(Language::get(code).unwrap().code, code);
+        }
+        assert!(Language::get("klingon").is_err());
+        assert_eq!(slugify("Zadanie ukończone!"), "zadanie ukończone");
+
+        let polish = Language::get("pol").unwrap();
+        assert_eq!(
+            parse_action_with("zadanie ukonczone\nprecious cargo", &THRESHOLDS, polish),
+            Some(Action::Quest("precious cargo".to_string()))
+        );
+        assert_eq!(parse_header("quest completed", &THRESHOLDS, polish), None);
+
+        let german = Language::get("deu").unwrap();
+        assert_eq!(
+            parse_action_with(
+                "neues handwerksdiagramm\ndiagramm: breitkopfbolzen",
+                &THRESHOLDS,
+                german
+            ),
+            Some(Action::Diagram("diagram broadhead bolt".to_string()))
+        );
+
+        let translation = "[headers]\nquest = \"Missione completata\"\nformula = \"Nuova formula \
+                           alchemica\"\ndiagram = \"Nuovo diagramma\"\n[quests]\n\"precious \
+                           cargo\" = \"Carico prezioso\"\n";
+        let italian = Language::translated("ita", translation).unwrap();
+        assert_eq!(
+            parse_action_with("missione completata\ncarico prezioso", &THRESHOLDS, &italian),
+            Some(Action::Quest("precious cargo".to_string()))
+        );
+        let unknown = translation.replace("precious cargo", "precious cargd");
+        assert!(Language::translated("ita", &unknown).is_err());
+    }
+
     #[test]
     fn test_tokenize_3() {
         println!("{:?}", parse_action("new alchemy formula manuscript page dancing st"));
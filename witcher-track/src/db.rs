@@ -4,6 +4,18 @@ use anyhow::Result;
 use rusqlite::Connection;
 
 use crate::data::{DEFAULT_DIAGRAMS, DEFAULT_FORMULAE, DIAGRAMS, FORMULAE, QUESTS};
+use crate::pipeline::{Stage, Timings};
+
+/// Result of flagging an item as found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagOutcome {
+    /// The item was not found before.
+    NewlyFound,
+    /// The item was already flagged as found.
+    AlreadyFound,
+    /// The item is not in the catalog.
+    UnknownItem,
+}
 
 /// Game run database handler.
 pub struct GameRun {
@@ -11,9 +23,18 @@ pub struct GameRun {
 }
 
 impl GameRun {
+    /// Open the progress database of the game run.
     pub fn new() -> Result<Self> {
-        let conn = Connection::open("tw3hundo.db")?;
+        Self::init(Connection::open("tw3hundo.db")?)
+    }
 
+    /// Open a database discarded when dropped, leaving the game run untouched.
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Create missing tables and catalog items.
+    fn init(conn: Connection) -> Result<Self> {
         conn.execute(
             r#"
             CREATE TABLE IF NOT EXISTS diagrams (
@@ -59,6 +80,16 @@ impl GameRun {
             "#,
             (),
         )?;
+        conn.execute(
+            r#"
+            CREATE TABLE IF NOT EXISTS stage_perf (
+                frame INT,
+                stage TEXT,
+                timing REAL
+            )
+            "#,
+            (),
+        )?;
 
         conn.execute("BEGIN TRANSACTION;", ())?;
 
@@ -118,21 +149,108 @@ impl GameRun {
         Ok(())
     }
 
-    pub fn flag_diagram(&mut self, diagram: &str) -> Result<()> {
-        self.conn.execute("UPDATE diagrams SET found = 1 WHERE diagram = ?", [diagram])?;
-        self.log("FOUND DIAGRAM", diagram)?;
+    /// Record how long each pipeline stage took on a frame.
+    pub fn stage_timings(&mut self, frame: usize, timings: &Timings) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT INTO stage_perf (frame, stage, timing) VALUES (?, ?, ?)")?;
+            for stage in Stage::ALL {
+                stmt.execute((frame, stage.as_str(), timings.get(stage).as_secs_f64()))?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
-    pub fn flag_formula(&mut self, formula: &str) -> Result<()> {
-        self.conn.execute("UPDATE formulae SET found = 1 WHERE formula = ?", [formula])?;
-        self.log("FOUND FORMULA", formula)?;
-        Ok(())
+    /// Log entries since the last `LOG` entry, oldest first.
+    pub fn session_logs(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT message, content FROM logs
+            WHERE rowid >= (SELECT IFNULL(MAX(rowid), 0) FROM logs WHERE message = 'LOG')
+            ORDER BY rowid
+            "#,
+        )?;
+        let logs = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(logs.collect::<rusqlite::Result<_>>()?)
     }
 
-    pub fn flag_quest(&mut self, quest: &str) -> Result<()> {
-        self.conn.execute("UPDATE quests SET found = 1 WHERE quest = ?", [quest])?;
-        self.log("FOUND QUEST", quest)?;
-        Ok(())
+    /// The `count` most recent frame timings in seconds, oldest first.
+    pub fn recent_timings(&self, count: usize) -> Result<Vec<f64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timing FROM (SELECT rowid, timing FROM perf ORDER BY rowid DESC LIMIT ?) \
+             ORDER BY rowid",
+        )?;
+        let timings = stmt.query_map([count], |row| row.get(0))?;
+        Ok(timings.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn flag_diagram(&mut self, diagram: &str) -> Result<FlagOutcome> {
+        self.flag("diagrams", "diagram", diagram, "DIAGRAM")
+    }
+
+    pub fn flag_formula(&mut self, formula: &str) -> Result<FlagOutcome> {
+        self.flag("formulae", "formula", formula, "FORMULA")
+    }
+
+    pub fn flag_quest(&mut self, quest: &str) -> Result<FlagOutcome> {
+        self.flag("quests", "quest", quest, "QUEST")
+    }
+
+    /// Mark an item as found in `table`, reporting whether it was already.
+    fn flag(&mut self, table: &str, column: &str, item: &str, kind: &str) -> Result<FlagOutcome> {
+        let updated = self.conn.execute(
+            &format!("UPDATE {table} SET found = 1 WHERE {column} = ? AND found = 0"),
+            [item],
+        )?;
+
+        let outcome = if updated > 0 {
+            FlagOutcome::NewlyFound
+        } else {
+            let exists = self
+                .conn
+                .prepare(&format!("SELECT 1 FROM {table} WHERE {column} = ?"))?
+                .exists([item])?;
+            if exists {
+                FlagOutcome::AlreadyFound
+            } else {
+                FlagOutcome::UnknownItem
+            }
+        };
+
+        match outcome {
+            FlagOutcome::NewlyFound => self.log(format!("FOUND {kind}"), item)?,
+            FlagOutcome::AlreadyFound => self.log(format!("DUPLICATE {kind}"), item)?,
+            FlagOutcome::UnknownItem => self.log(format!("UNKNOWN {kind}"), item)?,
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag() {
+        let mut game_run = GameRun::in_memory().unwrap();
+
+        assert_eq!(game_run.flag_quest("precious cargo").unwrap(), FlagOutcome::NewlyFound);
+        assert_eq!(game_run.flag_quest("precious cargo").unwrap(), FlagOutcome::AlreadyFound);
+        assert_eq!(game_run.flag_quest("no such quest").unwrap(), FlagOutcome::UnknownItem);
+        // Items known from the start are already found.
+        let default_formula = DEFAULT_FORMULAE.iter().next().unwrap();
+        assert_eq!(game_run.flag_formula(default_formula).unwrap(), FlagOutcome::AlreadyFound);
+
+        let logs = game_run.session_logs().unwrap();
+        let messages: Vec<_> = logs.iter().map(|(message, _)| message.as_str()).collect();
+        assert_eq!(messages, [
+            "FOUND QUEST",
+            "DUPLICATE QUEST",
+            "UNKNOWN QUEST",
+            "DUPLICATE FORMULA"
+        ]);
     }
 }
@@ -0,0 +1,125 @@
+//! Debouncing of parsed actions across consecutive frames.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::Action;
+
+/// How many agreeing detections are needed before an action is committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebouncePolicy {
+    /// Number of frames that must agree on the same action.
+    pub min_detections: usize,
+    /// Number of most recent frames the detections must fall within.
+    pub window: usize,
+}
+
+/// Filters per-frame detections so that each action is reported only once.
+///
+/// A notification stays on screen for several seconds, so the same action is
+/// parsed on many consecutive frames, while a single misread frame should not
+/// be trusted on its own. A committed action can be committed again once it
+/// has not been detected for a whole window, e.g. if the notification shows up
+/// again later.
+pub struct Debouncer {
+    policy: DebouncePolicy,
+    frame: usize,
+    detections: VecDeque<(usize, Action)>,
+    /// Committed actions, with the last frame they were detected on.
+    committed: HashMap<Action, usize>,
+}
+
+impl Debouncer {
+    /// Construct a new instance.
+    pub fn new(policy: DebouncePolicy) -> Self {
+        Self { policy, frame: 0, detections: VecDeque::new(), committed: HashMap::new() }
+    }
+
+    /// Feed the action parsed from the next frame, if any.
+    ///
+    /// Returns the action the first time it is confirmed by the policy, and
+    /// `None` for every later detection of the same action while it stays
+    /// within the window.
+    pub fn push(&mut self, action: Option<Action>) -> Option<Action> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        while let Some((oldest, _)) = self.detections.front() {
+            if frame - oldest < self.policy.window {
+                break;
+            }
+            self.detections.pop_front();
+        }
+        let window = self.policy.window;
+        self.committed.retain(|_, last_seen| frame - *last_seen < window);
+
+        let action = action?;
+        if let Some(last_seen) = self.committed.get_mut(&action) {
+            *last_seen = frame;
+            return None;
+        }
+        self.detections.push_back((frame, action.clone()));
+
+        let count = self.detections.iter().filter(|(_, detected)| *detected == action).count();
+        if count < self.policy.min_detections {
+            return None;
+        }
+
+        self.detections.retain(|(_, detected)| *detected != action);
+        self.committed.insert(action.clone(), frame);
+
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quest(name: &str) -> Option<Action> {
+        Some(Action::Quest(name.to_string()))
+    }
+
+    #[test]
+    fn test_debounce() {
+        let mut debouncer = Debouncer::new(DebouncePolicy { min_detections: 2, window: 3 });
+
+        assert_eq!(debouncer.push(quest("precious cargo")), None);
+        assert_eq!(debouncer.push(None), None);
+        assert_eq!(debouncer.push(quest("precious cargo")), quest("precious cargo"));
+        assert_eq!(debouncer.push(quest("precious cargo")), None);
+        assert_eq!(debouncer.push(quest("precious cargo")), None);
+    }
+
+    #[test]
+    fn test_debounce_window() {
+        let mut debouncer = Debouncer::new(DebouncePolicy { min_detections: 2, window: 3 });
+
+        assert_eq!(debouncer.push(quest("deserter gold")), None);
+        assert_eq!(debouncer.push(None), None);
+        assert_eq!(debouncer.push(quest("dirty funds")), None);
+        assert_eq!(debouncer.push(quest("deserter gold")), None);
+        assert_eq!(debouncer.push(quest("dirty funds")), quest("dirty funds"));
+        assert_eq!(debouncer.push(quest("deserter gold")), quest("deserter gold"));
+    }
+
+    #[test]
+    fn test_debounce_expiry() {
+        let mut debouncer = Debouncer::new(DebouncePolicy { min_detections: 2, window: 3 });
+
+        assert_eq!(debouncer.push(quest("precious cargo")), None);
+        assert_eq!(debouncer.push(quest("precious cargo")), quest("precious cargo"));
+        // Still on screen: every detection keeps the action committed.
+        for _ in 0..5 {
+            assert_eq!(debouncer.push(None), None);
+            assert_eq!(debouncer.push(quest("precious cargo")), None);
+        }
+
+        for _ in 0..3 {
+            assert_eq!(debouncer.push(None), None);
+        }
+        assert_eq!(debouncer.push(quest("precious cargo")), None);
+        assert_eq!(debouncer.push(quest("precious cargo")), quest("precious cargo"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Errors raised by the OCR reader and the preprocessing of frames.
+//!
+//! Setup errors are fatal, while errors on a single frame can be logged and
+//! the frame skipped.
+
+use std::fmt;
+
+use crate::locate::Region;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Tesseract could not be initialized with the language data.
+    Init { language: String },
+    /// No traineddata was found for a language.
+    MissingLanguageData { language: String },
+    /// Tesseract rejected a variable or its value.
+    Variable { name: String, value: String },
+    /// The picture could not be handed to Tesseract.
+    SetImage,
+    /// Tesseract failed to recognize a picture.
+    Recognize,
+    /// Tesseract returned no text where some was expected.
+    NullResult,
+    /// The worker reading the frame panicked.
+    Panicked,
+    /// The frame has no pixels to crop.
+    EmptyFrame { width: i32, height: i32 },
+    /// Leptonica could not crop or scale the frame.
+    Crop,
+    /// Leptonica could not convert the picture to color for masking.
+    ColorMask,
+    /// Leptonica could not copy a region of the picture, e.g. an empty one.
+    Clip { region: Region },
+}
+
+impl Error {
+    /// Whether the error only affects the current frame, as opposed to the
+    /// reader setup.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Error::Init { .. } | Error::MissingLanguageData { .. } | Error::Variable { .. } => {
+                false
+            },
+            Error::SetImage
+            | Error::Recognize
+            | Error::NullResult
+            | Error::Panicked
+            | Error::EmptyFrame { .. }
+            | Error::Crop
+            | Error::ColorMask
+            | Error::Clip { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Init { language } => write!(f, "Could not initialize Tesseract for {language}"),
+            Error::MissingLanguageData { language } => {
+                write!(f, "No traineddata for {language}")
+            },
+            Error::Variable { name, value } => {
+                write!(f, "Could not set Tesseract variable {name} to {value:?}")
+            },
+            Error::SetImage => write!(f, "Could not pass the picture to Tesseract"),
+            Error::Recognize => write!(f, "Tesseract could not recognize the picture"),
+            Error::NullResult => write!(f, "Tesseract returned no text"),
+            Error::Panicked => write!(f, "OCR worker panicked"),
+            Error::EmptyFrame { width, height } => {
+                write!(f, "Width and height are {width} {height}, can't crop")
+            },
+            Error::Crop => write!(f, "Could not crop the frame"),
+            Error::ColorMask => write!(f, "Could not convert picture to 32 bpp"),
+            Error::Clip { region } => write!(f, "Could not clip {region:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Whether an error, or any error it was caused by, only affects the current
+/// frame.
+pub fn is_recoverable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.downcast_ref::<Error>().is_some_and(Error::is_recoverable))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn test_is_recoverable() {
+        let frame_error = Err::<(), _>(Error::NullResult).context("Frame 12").unwrap_err();
+        assert!(is_recoverable(&frame_error));
+
+        let region = Region { x: 0, y: 10, width: 100, height: 0 };
+        assert!(is_recoverable(&Error::Clip { region }.into()));
+        assert!(is_recoverable(&Error::EmptyFrame { width: 0, height: 0 }.into()));
+
+        let setup_error = anyhow::Error::from(Error::Init { language: "eng".to_string() });
+        assert!(!is_recoverable(&setup_error));
+        assert!(!is_recoverable(&anyhow!("Capture failed")));
+    }
+}
@@ -0,0 +1,166 @@
+//! Cheap check on the color mask deciding whether a frame needs OCR.
+
+use std::fmt;
+use std::ptr::null_mut;
+use std::sync::Mutex;
+
+use leptonica_sys::*;
+use serde::{Deserialize, Serialize};
+
+use crate::picture::Picture;
+
+/// When the gate lets a frame through to OCR.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GatePolicy {
+    /// Fraction of the mask that must be set for the frame to hold any text.
+    pub min_text_fraction: f32,
+    /// Fraction of the mask that must differ from the last OCR'd frame for
+    /// its text to be read again.
+    pub min_change_fraction: f32,
+}
+
+/// Number of frames the gate let through or skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GateStats {
+    pub frames: usize,
+    /// Frames skipped because too few pixels were in the HSV range.
+    pub empty: usize,
+    /// Frames skipped because the mask matched the last OCR'd frame.
+    pub unchanged: usize,
+}
+
+impl GateStats {
+    pub fn skipped(&self) -> usize {
+        self.empty + self.unchanged
+    }
+}
+
+impl fmt::Display for GateStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames, {} skipped ({} empty, {} unchanged)",
+            self.frames,
+            self.skipped(),
+            self.empty,
+            self.unchanged
+        )
+    }
+}
+
+/// Skips OCR on frames without text, and on frames identical to the last one
+/// that went through OCR.
+///
+/// The gate can be shared by workers reading frames concurrently.
+pub struct FrameGate {
+    policy: GatePolicy,
+    state: Mutex<GateState>,
+}
+
+#[derive(Default)]
+struct GateState {
+    /// Mask of the last frame let through, and its text once recorded.
+    previous: Option<(Picture, Option<String>)>,
+    stats: GateStats,
+}
+
+impl FrameGate {
+    /// Construct a new instance.
+    pub fn new(policy: GatePolicy) -> Self {
+        Self { policy, state: Mutex::default() }
+    }
+
+    pub fn stats(&self) -> GateStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Check the color mask of a frame.
+    ///
+    /// Returns the text to use instead of running OCR, or `None` if OCR is
+    /// needed, in which case its result must be passed to [`Self::record`].
+    /// A frame matching one still being read goes through OCR as well.
+    pub fn check(&self, mask: &Picture) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        state.stats.frames += 1;
+        let area = unsafe { pixGetWidth(**mask) * pixGetHeight(**mask) } as f32;
+
+        if (count_pixels(mask) as f32) < area * self.policy.min_text_fraction {
+            state.stats.empty += 1;
+            state.previous = None;
+            return Some(String::new());
+        }
+
+        // Masks cropped to a differently sized notification box differ anyway.
+        let previous =
+            state.previous.as_ref().filter(|(pic, _)| dimensions(pic) == dimensions(mask));
+        if let Some((previous, Some(text))) = previous {
+            let diff = Picture::from(unsafe { pixXor(null_mut(), **previous, **mask) });
+            if !diff.is_null()
+                && (count_pixels(&diff) as f32) < area * self.policy.min_change_fraction
+            {
+                let text = text.clone();
+                state.stats.unchanged += 1;
+                return Some(text);
+            }
+        }
+
+        state.previous = Some((Picture::from(unsafe { pixCopy(null_mut(), **mask) }), None));
+        None
+    }
+
+    /// Remember the OCR result of a frame let through, unless another frame
+    /// was let through since.
+    pub fn record(&self, mask: &Picture, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((previous, previous_text)) = &mut state.previous {
+            let mut same = 0;
+            unsafe { pixEqual(**previous, **mask, &mut same) };
+            if same != 0 {
+                *previous_text = Some(text.to_string());
+            }
+        }
+    }
+}
+
+fn dimensions(pic: &Picture) -> (i32, i32) {
+    unsafe { (pixGetWidth(**pic), pixGetHeight(**pic)) }
+}
+
+/// Number of set pixels in a 1 bpp picture.
+fn count_pixels(pic: &Picture) -> i32 {
+    let mut count = 0;
+    unsafe { pixCountPixels(**pic, &mut count, null_mut()) };
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: GatePolicy = GatePolicy { min_text_fraction: 0.01, min_change_fraction: 0.01 };
+
+    fn mask(width: i32) -> Picture {
+        let pic = Picture::from(unsafe { pixCreate(100, 100, 1) });
+        unsafe { pixRasterop(*pic, 0, 0, width, 10, PIX_SET as _, null_mut(), 0, 0) };
+        pic
+    }
+
+    #[test]
+    fn test_gate() {
+        let gate = FrameGate::new(POLICY);
+
+        assert_eq!(gate.check(&mask(0)), Some(String::new()));
+        assert_eq!(gate.check(&mask(50)), None);
+        // The same frame is read again until the first read is recorded.
+        assert_eq!(gate.check(&mask(50)), None);
+        gate.record(&mask(50), "a");
+        assert_eq!(gate.check(&mask(50)), Some("a".to_string()));
+
+        // A result recorded after another frame was let through is dropped.
+        assert_eq!(gate.check(&mask(80)), None);
+        gate.record(&mask(50), "b");
+        assert_eq!(gate.check(&mask(80)), None);
+
+        assert_eq!(gate.stats(), GateStats { frames: 6, empty: 1, unchanged: 1 });
+    }
+}
@@ -1,22 +1,36 @@
 #![feature(iter_intersperse)]
 
-use std::ffi::CStr;
 use std::ops::Range;
-use std::ptr::null_mut;
-
-use anyhow::Result;
-use picture::Picture;
-use tesseract_sys::*;
 
+use data::{Threshold, Thresholds};
+use debounce::DebouncePolicy;
+use gate::GatePolicy;
+use locate::LocatePolicy;
+pub use ocr::OcrReader;
+use serde::{Deserialize, Serialize};
+
+pub mod cache;
+pub mod calibration;
+pub mod compare;
+pub mod config;
 pub mod data;
 pub mod db;
+pub mod debounce;
+pub mod error;
+pub mod gate;
+pub mod locate;
+pub mod ocr;
 pub mod picture;
+pub mod pipeline;
+pub mod report;
+pub mod roi;
 pub mod screenshot;
+pub mod synthetic;
+pub mod training;
+pub mod tuning;
+pub mod worker;
 
-// Tesseract trained data.
-const TRAINED_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/eng.traineddata"));
-// const TRAINED_DATA: &[u8] = include_bytes!("../witcher.traineddata");
-
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Crop {
     pub left: f32,
     pub right: f32,
@@ -25,81 +39,69 @@ pub struct Crop {
 }
 
 // pub const CROP_RANGE: (f32, f32) = (0.6, 0.25);
+/// Region of a `HUD_ASPECT` frame searched for the notification box, at HUD
+/// scale 1.
 pub const CROP_RANGE: Crop = Crop { left: 0.01, right: 0.5, top: 0.45, bottom: 0.35 };
-pub const HSV_RANGE: (Range<u8>, Range<u8>, Range<u8>) = (0..70, 20..120, 150..255);
-pub const STRSIM_THRESHOLD: f64 = 0.7;
-
-pub const TEST_CASES: &[&str] = &[
-    "tests/fixtures/immagine.jpg",
-    "tests/fixtures/immagine(1).jpg",
-    "tests/fixtures/immagine(2).jpg",
-    "tests/fixtures/immagine(3).jpg",
-    "tests/fixtures/immagine(4).jpg",
-    "tests/fixtures/immagine(5).jpg",
-    "tests/fixtures/immagine(6).jpg",
-    "tests/fixtures/immagine(7).jpg",
-    "tests/fixtures/immagine(8).jpg",
-    "tests/fixtures/mov000318.png",
-    "tests/fixtures/mov000735.png",
-    "tests/fixtures/mov000757.png",
-    "tests/fixtures/mov002859.png",
-    "tests/fixtures/mov002860.png",
-    "tests/fixtures/mov002905.png",
-    "tests/fixtures/mov008372.png",
-    "tests/fixtures/mov011049.png",
-    "tests/fixtures/mov011121.png",
-    "tests/fixtures/mov020521.png",
-];
-
-/// RAII wrapper around Tesseract API
-pub struct OcrReader {
-    handle: *mut TessBaseAPI,
+/// Aspect ratio the HUD is laid out for.
+pub const HUD_ASPECT: f32 = 16. / 9.;
+/// In-game HUD scale.
+pub const HUD_SCALE: f32 = 1.;
+/// Height of notification glyphs at HUD scale 1, as a fraction of the HUD
+/// height.
+pub const GLYPH_HEIGHT: f32 = 0.025;
+/// Glyph height in pixels that cropped regions are scaled to for Tesseract.
+pub const TARGET_GLYPH_HEIGHT: f32 = 54.;
+/// Range of each HSV channel, with hue in `0..240` as Leptonica computes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HsvRange {
+    pub hue: Range<u8>,
+    pub saturation: Range<u8>,
+    pub value: Range<u8>,
 }
 
-unsafe impl Send for OcrReader {}
-unsafe impl Sync for OcrReader {}
-
-impl OcrReader {
-    /// Construct a new instance.
-    pub fn new() -> Result<Self> {
-        let handle = unsafe { TessBaseAPICreate() };
-        unsafe {
-            TessBaseAPIInit5(
-                handle,
-                TRAINED_DATA.as_ptr() as *const i8,
-                TRAINED_DATA.len() as i32,
-                b"eng\0".as_ptr() as *const i8,
-                TessOcrEngineMode_OEM_LSTM_ONLY,
-                null_mut(),
-                0,
-                null_mut(),
-                null_mut(),
-                0,
-                1,
-            )
-        };
-
-        Ok(Self { handle })
-    }
-
-    /// Run OCR on a picture.
-    pub fn get_ocr(&self, image: &Picture) -> Result<String> {
-        unsafe { TessBaseAPISetImage2(self.handle, image.pix()) };
-
-        let text = unsafe { TessBaseAPIGetUTF8Text(self.handle) };
-        let text_str = unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned();
-
-        unsafe { TessDeleteText(text) };
-
-        Ok(text_str)
-    }
-}
-
-impl Drop for OcrReader {
-    fn drop(&mut self) {
-        unsafe { TessBaseAPIDelete(self.handle) };
-    }
-}
+pub const HSV_RANGE: HsvRange = HsvRange { hue: 0..70, saturation: 20..120, value: 150..255 };
+pub const THRESHOLDS: Thresholds = Thresholds {
+    quest: Threshold { header: 0.6, name: 0.7 },
+    formula: Threshold { header: 0.6, name: 0.7 },
+    diagram: Threshold { header: 0.6, name: 0.7 },
+};
+/// Localization of the notification box within the crop, in pixels of the
+/// scaled crop.
+pub const LOCATE_POLICY: LocatePolicy =
+    LocatePolicy { enabled: true, merge_width: 40, merge_height: 30, padding: 10 };
+/// Bounding box area of the mask components kept as glyphs.
+pub const COMPONENT_AREA: Range<i32> = 100..30000;
+/// Bounding box width over height of the mask components kept as glyphs.
+pub const COMPONENT_ASPECT_RATIO: Range<f32> = 0.0..1.0;
+/// Delay between two live captures, in milliseconds.
+pub const CAPTURE_INTERVAL_MS: u64 = 100;
+pub const DEBOUNCE_POLICY: DebouncePolicy = DebouncePolicy { min_detections: 2, window: 5 };
+pub const GATE_POLICY: GatePolicy =
+    GatePolicy { min_text_fraction: 0.002, min_change_fraction: 0.001 };
+/// OCR results kept for frames read again.
+pub const OCR_CACHE_SIZE: usize = 16;
+/// Threads reading live frames.
+pub const OCR_WORKERS: usize = 2;
+/// Live frames waiting for a worker, beyond which the oldest is dropped.
+pub const FRAME_QUEUE_SIZE: usize = 4;
+/// Config loaded at startup unless `--config` is given, and written by the
+/// `calibrate` command.
+pub const CONFIG_PATH: &str = "witcher-track.toml";
+/// Traineddata fine-tuned on notification fonts, loaded by
+/// [`ocr::Model::Witcher`].
+pub const WITCHER_MODEL_PATH: &str = "witcher.traineddata";
+/// Directory Tesseract models of other languages are read from, by
+/// [`ocr::Model::Language`].
+pub const TESSDATA_DIR: &str = "tessdata";
+/// Tesseract code of the language the game runs in.
+pub const LANGUAGE: &str = "eng";
+/// Fixture screenshots and the action expected on each, read by the tests and
+/// the `compare` command.
+pub const GROUND_TRUTH_PATH: &str = "tests/fixtures/ground_truth.txt";
+/// Directory the `ground-truth` command writes tesstrain lines to by default.
+pub const TRAINING_DIR: &str = "target/witcher-ground-truth";
+/// Number of rendering variations of each training line.
+pub const TRAINING_VARIATIONS: u64 = 3;
 
 #[cfg(test)]
 mod tests {
@@ -107,44 +109,141 @@ mod tests {
     use std::time::Instant;
 
     use super::*;
-    use crate::data::parse_action;
-    use crate::picture::preprocess;
+    use crate::cache::OcrCache;
+    use crate::compare::{self, Fixture};
+    use crate::config::TrackerConfig;
+    use crate::data::{parse_action, parse_header, Action, Language};
+    use crate::gate::FrameGate;
+    use crate::locate::Region;
+    use crate::picture::Picture;
+    use crate::pipeline::{self, Stage, Timings};
+
+    fn ground_truth() -> Vec<Fixture> {
+        compare::load_fixtures(GROUND_TRUTH_PATH).unwrap()
+    }
+
+    /// Whether each stage of the pipeline produced the expected result.
+    struct StageResults {
+        /// The crop holds text in the notification colors exactly when a
+        /// notification is expected.
+        crop: bool,
+        /// The located box spans the header and name lines of the expected
+        /// notification.
+        preprocess: bool,
+        header: bool,
+        parse: bool,
+    }
 
-    fn run_ocr(ocr_reader: &OcrReader, path: &str) {
+    /// Height range of a located box holding a header and a name of one or
+    /// two lines, in glyph heights of the scaled crop.
+    const BOX_GLYPHS: Range<f32> = 1.5..6.;
+
+    fn is_notification_box(region: Region, cropped: &Picture) -> bool {
+        let glyphs = region.height as f32 / TARGET_GLYPH_HEIGHT;
+        region != Region::of(cropped) && BOX_GLYPHS.contains(&glyphs)
+    }
+
+    fn run_ocr(ocr_reader: &OcrReader, case: &Fixture) -> StageResults {
+        let mut results =
+            StageResults { crop: false, preprocess: false, header: false, parse: false };
+        let path = &case.path;
         let start = Instant::now();
         let data = fs::read(path).unwrap();
+        let mut timings = Timings::default();
 
-        let start_crop = Instant::now();
-        let pic = Picture::from_mem(data).into_cropped().unwrap();
-        let elapsed_crop = start_crop.elapsed();
+        let config = TrackerConfig::default();
 
-        let start_preprocess = Instant::now();
-        let pic = unsafe { preprocess(pic).unwrap() };
-        let elapsed_preprocess = start_preprocess.elapsed();
+        let Ok(pic) = pipeline::crop(Picture::from_mem(data), &config, &mut timings) else {
+            return results;
+        };
+        let Ok((region, bin_pic)) = pipeline::locate_box(&pic, &config, &mut timings) else {
+            return results;
+        };
+        let has_text = FrameGate::new(config.gate).check(&bin_pic).is_none();
+        results.crop = has_text == case.expected.is_some();
+        // Without a notification, there is no box to locate.
+        results.preprocess = match case.expected {
+            Some(_) => is_notification_box(region, &pic),
+            None => results.crop,
+        };
+
+        let cache = OcrCache::new(0);
+        let res =
+            pipeline::read_box(ocr_reader, &config, &cache, &pic, region, bin_pic, &mut timings);
 
-        let start_ocr = Instant::now();
-        let res = ocr_reader.get_ocr(&pic);
-        let elapsed_ocr = start_ocr.elapsed();
+        let tok = timings.time(Stage::Parse, || res.as_ref().ok().and_then(parse_action));
 
-        let start_parse = Instant::now();
-        let tok = res.as_ref().ok().and_then(parse_action);
-        let elapsed_parse = start_parse.elapsed();
+        let header =
+            res.as_ref().ok().and_then(|text| parse_header(text, &THRESHOLDS, Language::english()));
+        results.header = header == case.expected.as_ref().map(Action::action_type);
+        results.parse = tok == case.expected;
 
         let elapsed = start.elapsed();
         println!("--- test case ---");
         println!("{path}\n{res:?}\n{tok:?}\nTook:");
         println!("  All         {elapsed:?}");
-        println!("  Crop        {elapsed_crop:?}");
-        println!("  Preprocess  {elapsed_preprocess:?}");
-        println!("  Ocr         {elapsed_ocr:?}");
-        println!("  Parse       {elapsed_parse:?}");
+        for stage in &Stage::ALL[1..] {
+            println!("  {:<12}{:?}", stage.as_str(), timings.get(*stage));
+        }
+
+        results
     }
 
     #[test]
     fn test_ocr() {
         let ocr_reader = OcrReader::new().unwrap();
-        for test_case in TEST_CASES {
-            run_ocr(&ocr_reader, test_case);
+        let cases = ground_truth();
+        let results: Vec<_> = cases.iter().map(|case| run_ocr(&ocr_reader, case)).collect();
+
+        let mark = |ok: bool| if ok { "ok" } else { "FAIL" };
+        let accuracy = |stage: fn(&StageResults) -> bool| {
+            100. * results.iter().filter(|r| stage(r)).count() as f64 / results.len() as f64
+        };
+
+        println!("{:<36} {:>6} {:>6} {:>6} {:>6}", "fixture", "crop", "prep", "header", "parse");
+        for (case, r) in cases.iter().zip(&results) {
+            println!(
+                "{:<36} {:>6} {:>6} {:>6} {:>6}",
+                case.path,
+                mark(r.crop),
+                mark(r.preprocess),
+                mark(r.header),
+                mark(r.parse)
+            );
+        }
+        println!(
+            "{:<36} {:>5.0}% {:>5.0}% {:>5.0}% {:>5.0}%",
+            "accuracy",
+            accuracy(|r| r.crop),
+            accuracy(|r| r.preprocess),
+            accuracy(|r| r.header),
+            accuracy(|r| r.parse)
+        );
+
+        let failures: Vec<_> = cases
+            .iter()
+            .zip(&results)
+            .filter(|(case, r)| !case.known_failure && !r.parse)
+            .map(|(case, _)| case.path.as_str())
+            .collect();
+        assert!(failures.is_empty(), "Fixtures not parsed as expected: {failures:?}");
+    }
+
+    #[test]
+    fn test_min_confidence() {
+        // Keeping every word rebuilds the text from words, which must split
+        // the header and name lines as Tesseract does.
+        let config = TrackerConfig::default();
+        let ocr_reader = OcrReader::new().unwrap();
+        let words_reader = OcrReader::builder().min_confidence(0.).build().unwrap();
+
+        for case in ground_truth() {
+            let read = |reader| {
+                let screenshot = Picture::from_mem(fs::read(&case.path).unwrap());
+                pipeline::read_frame(reader, &config, screenshot).unwrap().0
+            };
+            let (text, words_text) = (read(&ocr_reader), read(&words_reader));
+            assert_eq!(parse_action(&words_text), parse_action(&text), "{}", case.path);
         }
     }
 }
@@ -0,0 +1,190 @@
+//! Localization of the notification box inside the cropped search region.
+//!
+//! The crop only bounds where notifications can appear. The box itself is
+//! found on the color mask by merging glyphs into blocks and keeping the
+//! largest block, so that HUD mods, resolution changes and notifications
+//! sliding in don't push the text out of a fixed rectangle.
+
+use std::ops::Range;
+use std::ptr::null_mut;
+
+use leptonica_sys::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::picture::Picture;
+
+/// How glyphs are merged into text blocks, and how much to keep around them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LocatePolicy {
+    /// Crop the mask to the notification box. Otherwise the whole search
+    /// region goes to OCR.
+    pub enabled: bool,
+    /// Gaps up to this wide between glyphs are merged, in pixels.
+    pub merge_width: i32,
+    /// Gaps up to this high between lines are merged, in pixels.
+    pub merge_height: i32,
+    /// Margin kept around the located box, in pixels.
+    pub padding: i32,
+}
+
+/// Rectangle of a picture, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Region {
+    /// Region covering a whole picture.
+    pub fn of(pic: &Picture) -> Region {
+        unsafe { Region { x: 0, y: 0, width: pixGetWidth(**pic), height: pixGetHeight(**pic) } }
+    }
+
+    /// Horizontal band of the region, with `rows` as fractions of its height.
+    pub fn rows(&self, rows: &Range<f32>) -> Region {
+        let top = (self.height as f32 * rows.start) as i32;
+        let bottom = (self.height as f32 * rows.end) as i32;
+
+        Region { x: self.x, y: self.y + top, width: self.width, height: bottom - top }
+    }
+
+    /// Smallest region containing both regions.
+    pub fn union(&self, other: &Region) -> Region {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        Region { x, y, width: right - x, height: bottom - y }
+    }
+
+    fn area(&self) -> i32 {
+        self.width * self.height
+    }
+
+    /// Grow the region by `padding` on every side, staying within `bounds`.
+    fn padded(&self, padding: i32, bounds: Region) -> Region {
+        let x = (self.x - padding).max(bounds.x);
+        let y = (self.y - padding).max(bounds.y);
+        let right = (self.x + self.width + padding).min(bounds.x + bounds.width);
+        let bottom = (self.y + self.height + padding).min(bounds.y + bounds.height);
+
+        Region { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+/// Find the notification box in a color mask, as the largest block of
+/// merged glyphs.
+pub fn locate(mask: &Picture, policy: &LocatePolicy) -> Option<Region> {
+    let blocks = unsafe {
+        let merged = Picture::from(pixCloseBrick(
+            null_mut(),
+            **mask,
+            policy.merge_width.max(1),
+            policy.merge_height.max(1),
+        ));
+        if merged.is_null() {
+            return None;
+        }
+        components(&merged)
+    };
+
+    let bounds = Region::of(mask);
+    blocks.into_iter().max_by_key(Region::area).map(|block| block.padded(policy.padding, bounds))
+}
+
+/// Region of a color mask to keep: the notification box, or the whole mask
+/// if there is no text or localization is disabled.
+pub fn box_region(mask: &Picture, policy: &LocatePolicy) -> Region {
+    let located = if policy.enabled { locate(mask, policy) } else { None };
+    located.unwrap_or_else(|| Region::of(mask))
+}
+
+/// Copy a region of a picture.
+pub fn clip(pic: &Picture, region: Region) -> Result<Picture, Error> {
+    let clipped = unsafe {
+        let mut boxx = boxCreate(region.x, region.y, region.width, region.height);
+        let clipped = Picture::from(pixClipRectangle(**pic, boxx, null_mut()));
+        boxDestroy(&mut boxx);
+        clipped
+    };
+
+    if clipped.is_null() {
+        Err(Error::Clip { region })
+    } else {
+        Ok(clipped)
+    }
+}
+
+/// Bounding boxes of the connected components of a 1 bpp picture.
+unsafe fn components(bin_pic: &Picture) -> Vec<Region> {
+    let mut boxa = pixConnComp(**bin_pic, null_mut(), 8);
+    if boxa.is_null() {
+        return Vec::new();
+    }
+
+    let regions = (0..boxaGetCount(boxa))
+        .map(|i| {
+            let mut bbox = boxaGetBox(boxa, i, L_CLONE as _);
+            let (mut x, mut y, mut width, mut height) = (0, 0, 0, 0);
+            boxGetGeometry(bbox, &mut x, &mut y, &mut width, &mut height);
+            boxDestroy(&mut bbox);
+            Region { x, y, width, height }
+        })
+        .collect();
+    boxaDestroy(&mut boxa);
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: LocatePolicy =
+        LocatePolicy { enabled: true, merge_width: 10, merge_height: 10, padding: 2 };
+
+    fn mask(set: &[(i32, i32, i32, i32)]) -> Picture {
+        let pic = Picture::from(unsafe { pixCreate(200, 100, 1) });
+        for &(x, y, w, h) in set {
+            unsafe { pixRasterop(*pic, x, y, w, h, PIX_SET as _, null_mut(), 0, 0) };
+        }
+        pic
+    }
+
+    #[test]
+    fn test_locate() {
+        // Two lines of glyphs, and a stray blob far from them.
+        let glyphs = [(20, 20, 8, 10), (32, 20, 8, 10), (20, 36, 30, 10), (180, 90, 4, 4)];
+        let pic = mask(&glyphs);
+
+        assert_eq!(locate(&pic, &POLICY), Some(Region { x: 18, y: 18, width: 34, height: 30 }));
+        assert_eq!(locate(&mask(&[]), &POLICY), None);
+
+        let cropped = clip(&pic, box_region(&pic, &POLICY)).unwrap();
+        assert_eq!(unsafe { (pixGetWidth(*cropped), pixGetHeight(*cropped)) }, (34, 30));
+
+        let disabled = LocatePolicy { enabled: false, ..POLICY };
+        let whole = clip(&pic, box_region(&pic, &disabled)).unwrap();
+        assert_eq!(unsafe { (pixGetWidth(*whole), pixGetHeight(*whole)) }, (200, 100));
+        let empty = mask(&[]);
+        assert_eq!(box_region(&empty, &POLICY), Region::of(&empty));
+    }
+
+    #[test]
+    fn test_rows() {
+        let region = Region { x: 10, y: 20, width: 100, height: 50 };
+        assert_eq!(region.rows(&(0.0..0.4)), Region { x: 10, y: 20, width: 100, height: 20 });
+        assert_eq!(region.rows(&(0.4..1.0)), Region { x: 10, y: 40, width: 100, height: 30 });
+    }
+
+    #[test]
+    fn test_padded() {
+        let bounds = Region { x: 0, y: 0, width: 100, height: 50 };
+        let region = Region { x: 1, y: 10, width: 20, height: 38 };
+        assert_eq!(region.padded(4, bounds), Region { x: 0, y: 6, width: 25, height: 44 });
+    }
+}
@@ -1,75 +1,408 @@
 use std::io::Read;
-use std::thread;
 use std::time::{Duration, Instant};
+use std::{env, fs, thread};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tracing::metadata::LevelFilter;
-use witcher_track::data::{parse_action, Action};
-use witcher_track::db::GameRun;
-use witcher_track::picture::{preprocess, Picture};
+use witcher_track::config::TrackerConfig;
+use witcher_track::data::{
+    parse_action_scored, parse_action_with, slugify, Action, ActionType, Language, Thresholds,
+};
+use witcher_track::db::{FlagOutcome, GameRun};
+use witcher_track::debounce::Debouncer;
+use witcher_track::ocr::{Model, OcrReaderBuilder};
+use witcher_track::picture::Picture;
+use witcher_track::pipeline::{Stage, Timings};
+use witcher_track::report::{self, Report};
 use witcher_track::screenshot::MovPng;
-use witcher_track::{screenshot, OcrReader};
+use witcher_track::worker::{Worker, WorkerPool, WorkerStats};
+use witcher_track::{
+    calibration, compare, error, pipeline, screenshot, training, tuning, CONFIG_PATH,
+    GROUND_TRUTH_PATH, TRAINING_DIR, TRAINING_VARIATIONS,
+};
+
+/// Number of live frames between two logs of the worker counters.
+const STATS_LOG_INTERVAL: usize = 600;
+
+/// State carried across the frames of a tracking session.
+struct Session {
+    game_run: GameRun,
+    debouncer: Debouncer,
+    thresholds: Thresholds,
+    language: &'static Language,
+}
+
+impl Session {
+    fn new(config: &TrackerConfig, game_run: GameRun) -> Result<Self> {
+        Ok(Self {
+            game_run,
+            debouncer: Debouncer::new(config.debounce),
+            thresholds: config.thresholds,
+            language: config.language()?,
+        })
+    }
+
+    /// Log the gate and cache counters of the workers.
+    fn log_stats(&mut self, stats: WorkerStats) -> Result<()> {
+        self.game_run.log("GATE", stats.gate.to_string())?;
+        self.game_run.log("CACHE", stats.cache.to_string())
+    }
+}
+
+fn ocr_loop(
+    session: &mut Session,
+    frame: usize,
+    ocr_text: String,
+    mut timings: Timings,
+) -> Result<Timings> {
+    let Session { game_run, debouncer, thresholds, language } = session;
 
-fn ocr_loop(game_run: &mut GameRun, ocr_reader: &OcrReader, screenshot: Picture) -> Result<()> {
-    let start = Instant::now();
-    let screenshot = unsafe { preprocess(screenshot)? };
-    let cropped = screenshot.into_cropped()?;
-    let ocr_text = ocr_reader.get_ocr(&cropped)?;
     if !ocr_text.trim().is_empty() {
         game_run.log("RECOGNIZED", &ocr_text)?;
     }
-    match parse_action(ocr_text) {
-        Some(Action::Quest(v)) => game_run.flag_quest(&v)?,
-        Some(Action::Formula(v)) => game_run.flag_formula(&v)?,
-        Some(Action::Diagram(v)) => game_run.flag_diagram(&v)?,
-        None => (),
+    let action = timings.time(Stage::Parse, || parse_action_with(ocr_text, thresholds, language));
+    if let Some(action) = debouncer.push(action) {
+        let outcome = match &action {
+            Action::Quest(v) => game_run.flag_quest(v)?,
+            Action::Formula(v) => game_run.flag_formula(v)?,
+            Action::Diagram(v) => game_run.flag_diagram(v)?,
+        };
+        // Parsed names come from the catalogs the database is filled from, so
+        // this points at a parser or catalog bug rather than a misread.
+        if outcome == FlagOutcome::UnknownItem {
+            tracing::warn!("Detected {action:?}, which is not in the database");
+        }
     }
-    game_run.timing(start.elapsed())?;
+    game_run.timing(timings.total())?;
+    game_run.stage_timings(frame, &timings)?;
 
-    Ok(())
+    Ok(timings)
 }
 
 // Test loop
-fn run_test() -> Result<()> {
+fn run_test(config: &TrackerConfig) -> Result<()> {
+    replay(config, |_| ())?;
+    Ok(())
+}
+
+// Replay the test recording on a single worker, handing the stage timings of
+// each frame to `on_frame`. Items are flagged in an in-memory database, which
+// is returned with the worker stats.
+fn replay(
+    config: &TrackerConfig,
+    mut on_frame: impl FnMut(Timings),
+) -> Result<(GameRun, WorkerStats)> {
     ansi_term::enable_ansi_support().unwrap();
-    let ocr_reader = OcrReader::new()?;
-    let mut game_run = GameRun::new()?;
+    let mut session = Session::new(config, GameRun::in_memory()?)?;
+    let worker = Worker::new(config)?;
     let mut movpng = MovPng::new();
 
     tracing_subscriber::fmt().with_max_level(LevelFilter::INFO).init();
-    game_run.log("LOG", "Started test run")?;
+    session.game_run.log("LOG", "Started test run")?;
+    session.game_run.log("OCR", config.ocr_settings().to_string())?;
 
     loop {
         let Some((idx, screenshot)) = movpng.next() else {
             break;
         };
-        ocr_loop(&mut game_run, &ocr_reader, screenshot)?;
-        game_run.log("FRAME", &format!("{idx}"))?;
+        let (ocr_text, timings) = worker.process(|| Ok(screenshot))?;
+        on_frame(ocr_loop(&mut session, idx, ocr_text, timings)?);
+        session.game_run.log("FRAME", format!("{idx}"))?;
     }
 
-    Ok(())
+    session.log_stats(worker.stats())?;
+
+    Ok((session.game_run, worker.stats()))
 }
 
 // Normal loop
-fn run() -> Result<()> {
+fn run(config: &TrackerConfig) -> Result<()> {
     ansi_term::enable_ansi_support().unwrap();
-    let ocr_reader = OcrReader::new()?;
-    let mut game_run = GameRun::new()?;
+    let mut session = Session::new(config, GameRun::new()?)?;
+    let mut pool = WorkerPool::new(config)?;
 
     tracing_subscriber::fmt().with_max_level(LevelFilter::INFO).init();
-    game_run.log("LOG", "Started")?;
+    session.game_run.log("LOG", "Started")?;
+    session.game_run.log("OCR", config.ocr_settings().to_string())?;
 
-    loop {
-        let screenshot = screenshot::capture()?;
+    let queue = pool.queue();
+    let capture_interval = config.capture_interval();
+    let capture = thread::spawn(move || -> Result<()> {
+        loop {
+            let start = Instant::now();
+            let screenshot = screenshot::capture().inspect_err(|_| queue.close())?;
+            if !queue.push(screenshot, start.elapsed()) {
+                return Ok(());
+            }
+
+            thread::sleep(capture_interval);
+        }
+    });
+
+    while let Some((frame, result)) = pool.next() {
+        // Dropped frames are counted by the queue.
+        match result {
+            Some(Ok((ocr_text, timings))) => {
+                ocr_loop(&mut session, frame, ocr_text, timings)?;
+            },
+            // A frame that failed on its own is skipped, setup errors end the run.
+            Some(Err(e)) if error::is_recoverable(&e) => {
+                session.game_run.log("ERROR", format!("Frame {frame}: {e:#}"))?;
+            },
+            Some(Err(e)) => return Err(e),
+            None => (),
+        }
+
+        if (frame + 1) % STATS_LOG_INTERVAL == 0 {
+            session.log_stats(pool.stats())?;
+            session.game_run.log("DROPPED", pool.queue().dropped().to_string())?;
+        }
+    }
+
+    capture.join().map_err(|_| anyhow!("Capture thread panicked"))?
+}
+
+// Per-stage timing percentiles over the test replay
+fn run_bench(config: &TrackerConfig) -> Result<()> {
+    let mut frames = Vec::new();
+    let (_, stats) = replay(config, |timings| frames.push(timings))?;
+
+    println!("Gate:  {}", stats.gate);
+    println!("Cache: {}", stats.cache);
+    println!("stage         p50       p90       p99       max");
+    let rows = Stage::ALL
+        .iter()
+        .map(|&stage| (stage.as_str(), frames.iter().map(|t| t.get(stage)).collect::<Vec<_>>()))
+        .chain([("total", frames.iter().map(Timings::total).collect())]);
+    for (name, mut durations) in rows {
+        durations.sort();
+        println!(
+            "{name:<12} {:>8.2?} {:>8.2?} {:>8.2?} {:>8.2?}",
+            pipeline::percentile(&durations, 50.),
+            pipeline::percentile(&durations, 90.),
+            pipeline::percentile(&durations, 99.),
+            pipeline::percentile(&durations, 100.),
+        );
+    }
+
+    Ok(())
+}
+
+// Threshold sweep over the test replay
+fn run_tune(labels_path: &str, config: &TrackerConfig) -> Result<()> {
+    let labels = tuning::parse_labels(&fs::read_to_string(labels_path)?)?;
+    let worker = Worker::new(config)?;
+
+    let frames = MovPng::new()
+        .map(|(idx, screenshot)| {
+            let (text, _) = worker.process(|| Ok(screenshot))?;
+            Ok((idx, text))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for action_type in ActionType::ALL {
+        let name = action_type.as_str();
+        let results =
+            tuning::sweep(&frames, &labels, &config.thresholds, config.language()?, action_type);
+
+        println!("--- {name} ---");
+        println!("header  name  precision  recall");
+        for (threshold, score) in &results {
+            println!(
+                "{:.2}    {:.2}  {:.3}      {:.3}",
+                threshold.header,
+                threshold.name,
+                score.precision(),
+                score.recall()
+            );
+        }
+
+        let (best, _) = results
+            .iter()
+            .max_by(|(_, score1), (_, score2)| score1.f1().partial_cmp(&score2.f1()).unwrap())
+            .ok_or_else(|| anyhow!("No thresholds swept"))?;
+        println!(
+            "Best: --threshold {name}.header={:.2} --threshold {name}.name={:.2}",
+            best.header, best.name
+        );
+    }
+
+    Ok(())
+}
+
+// Accuracy report of a fresh test replay against a labels file
+fn run_report(labels_path: &str, config: &TrackerConfig) -> Result<()> {
+    let labels = tuning::parse_labels(&fs::read_to_string(labels_path)?)?;
+    let (game_run, _) = replay(config, |_| ())?;
+
+    let logs = game_run.session_logs()?;
+    let frames = logs.iter().filter(|(message, _)| message == "FRAME").count();
+    let timings = game_run.recent_timings(frames)?;
+
+    print!("{}", Report::new(labels, report::detections(&logs), config.debounce.window, timings));
+
+    Ok(())
+}
+
+// Sweep the crop and HSV ranges to best read the expected item on a screenshot
+fn run_calibrate(
+    screenshot_path: &str,
+    expected: Action,
+    config: &TrackerConfig,
+    config_path: &str,
+) -> Result<()> {
+    let screenshot = Picture::from_file(screenshot_path.to_string());
+    if screenshot.is_null() {
+        return Err(anyhow!("Could not read screenshot {screenshot_path:?}"));
+    }
+    let ocr_reader = OcrReaderBuilder::from(config.ocr_settings()).build()?;
+    let language = config.language()?;
+
+    // Accept any name, so that a near miss still scores above a blank read.
+    let mut thresholds = config.thresholds;
+    thresholds.get_mut(expected.action_type()).name = 0.;
+
+    let score = |config: &TrackerConfig| {
+        let (text, _) = pipeline::read_frame(&ocr_reader, config, screenshot.clone())?;
+
+        Ok(match parse_action_scored(text, &thresholds, language) {
+            Some((action, score)) if action == expected => score,
+            _ => 0.,
+        })
+    };
+
+    let before = score(config)?;
+    let (config, after) = calibration::sweep(config.clone(), score)?;
+    println!("Score: {before:.3} -> {after:.3}");
+    println!("{:#?}\n{:#?}", config.crop, config.hsv);
+
+    calibration::write_stages(&screenshot, &config, "calibration")?;
+    // Only the swept ranges are saved, not the command line overrides.
+    let mut saved = TrackerConfig::load_or_default(config_path)?;
+    saved.crop = config.crop;
+    saved.hsv = config.hsv;
+    saved.save(config_path)?;
+    println!("Saved to {config_path}, stages written to calibration/");
+
+    Ok(())
+}
 
-        ocr_loop(&mut game_run, &ocr_reader, screenshot)?;
+// Fixture accuracy and OCR time of two models
+fn run_compare(models: [Model; 2], config: &TrackerConfig) -> Result<()> {
+    let fixtures = compare::load_fixtures(GROUND_TRUTH_PATH)?;
+    let readers = models
+        .iter()
+        .map(|model| OcrReaderBuilder::from(config.ocr_settings()).model(model.clone()).build())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mark = |ok: bool| if ok { "ok" } else { "FAIL" };
+    let [a, b] = &models;
+    println!("{:<36} {:>12} {:>12}", "fixture", a.to_string(), b.to_string());
+
+    let mut correct = [0; 2];
+    let mut elapsed = [Duration::ZERO; 2];
+    for fixture in &fixtures {
+        let mut row = format!("{:<36}", fixture.path);
+        for (i, reader) in readers.iter().enumerate() {
+            let reading = compare::read_fixture(reader, config, fixture)?;
+            let ok = reading.is_correct(fixture);
+            correct[i] += ok as usize;
+            elapsed[i] += reading.elapsed;
+            row += &format!(" {:>12}", mark(ok));
+        }
+        if fixture.known_failure {
+            row += "  (known failure)";
+        }
+        println!("{row}");
+    }
+
+    let accuracy = |correct: usize| 100. * correct as f64 / fixtures.len() as f64;
+    println!("{:<36} {:>11.0}% {:>11.0}%", "accuracy", accuracy(correct[0]), accuracy(correct[1]));
+    let mean = |elapsed: Duration| elapsed / fixtures.len().max(1) as u32;
+    println!("{:<36} {:>12.2?} {:>12.2?}", "mean time", mean(elapsed[0]), mean(elapsed[1]));
+
+    Ok(())
+}
+
+// Render catalog lines for fine-tuning the model with tesstrain
+fn run_ground_truth(dir: &str, config: &TrackerConfig) -> Result<()> {
+    let count = training::write_ground_truth(dir, config, TRAINING_VARIATIONS)?;
+    println!("Wrote {count} lines to {dir}");
+
+    Ok(())
+}
+
+fn run_command(args: &[String]) -> Result<()> {
+    let (command, args) = match args.split_first() {
+        Some((command, rest)) if !command.starts_with("--") => (command.as_str(), rest),
+        _ => ("run", args),
+    };
+
+    let mut config_path = None;
+    let mut overrides = Vec::new();
+    let mut model = None;
+    let mut language = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                config_path = Some(args.next().ok_or_else(|| anyhow!("--config needs a path"))?)
+            },
+            "--threshold" => {
+                overrides.push(args.next().ok_or_else(|| anyhow!("--threshold needs a value"))?)
+            },
+            "--model" => model = Some(args.next().ok_or_else(|| anyhow!("--model needs a value"))?),
+            "--language" => {
+                language = Some(args.next().ok_or_else(|| anyhow!("--language needs a code"))?)
+            },
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    // An explicit config must exist, the default one is optional.
+    let mut config = match config_path {
+        Some(path) => TrackerConfig::load(path)?,
+        None => TrackerConfig::load_or_default(CONFIG_PATH)?,
+    };
+    for setting in overrides {
+        config.thresholds.set(setting)?;
+    }
+    if let Some(language) = language {
+        config.language = language.clone();
+    }
+    if let Some(model) = model {
+        config.ocr.model = Some(Model::from(model.as_str()));
+    }
+    config.validate()?;
 
-        thread::sleep(Duration::from_millis(100));
+    match (command, positional.as_slice()) {
+        ("run", []) => run(&config),
+        ("test", []) => run_test(&config),
+        ("bench", []) => run_bench(&config),
+        ("tune", [labels_path]) => run_tune(labels_path, &config),
+        ("report", [labels_path]) => run_report(labels_path, &config),
+        ("compare", []) => run_compare([Model::Eng, Model::Witcher], &config),
+        ("compare", [a, b]) => run_compare([Model::from(*a), Model::from(*b)], &config),
+        ("ground-truth", []) => run_ground_truth(TRAINING_DIR, &config),
+        ("ground-truth", [dir]) => run_ground_truth(dir, &config),
+        ("calibrate", [screenshot_path, action_type, name]) => {
+            let expected = Action::new(action_type.parse()?, slugify(*name));
+            let config_path = config_path.map_or(CONFIG_PATH, String::as_str);
+            run_calibrate(screenshot_path, expected, &config, config_path)
+        },
+        _ => Err(anyhow!(
+            "Usage: witcher-track [run | test | bench | tune <labels> | report <labels> | \
+             calibrate <screenshot> <type> <name> | compare [<model> <model>] | ground-truth \
+             [<dir>]] [--config <path>] [--language <code>] [--model <eng|witcher|code|path>] \
+             [--threshold <type>.<header|name>=<value>]..."
+        )),
     }
 }
 
 fn main() {
-    if let Err(e) = run() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run_command(&args) {
         eprintln!("Errored out: {e:#?}");
         let _ = std::io::stdin().read(&mut [0u8]).unwrap();
     }
@@ -0,0 +1,603 @@
+//! Tesseract reader, the settings it is built with and the words it
+//! recognizes.
+
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::{fmt, fs};
+
+use leptonica_sys::{pixGetHeight, pixGetWidth};
+use serde::{Deserialize, Serialize};
+use tesseract_sys::*;
+
+use crate::error::Error;
+use crate::locate::Region;
+use crate::picture::Picture;
+use crate::{TESSDATA_DIR, WITCHER_MODEL_PATH};
+
+/// How Tesseract splits a picture into lines and words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSegMode {
+    Auto,
+    SingleColumn,
+    SingleBlock,
+    SingleLine,
+    SingleWord,
+    SparseText,
+    RawLine,
+}
+
+impl PageSegMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PageSegMode::Auto => "auto",
+            PageSegMode::SingleColumn => "single_column",
+            PageSegMode::SingleBlock => "single_block",
+            PageSegMode::SingleLine => "single_line",
+            PageSegMode::SingleWord => "single_word",
+            PageSegMode::SparseText => "sparse_text",
+            PageSegMode::RawLine => "raw_line",
+        }
+    }
+
+    fn to_tess(self) -> TessPageSegMode {
+        match self {
+            PageSegMode::Auto => TessPageSegMode_PSM_AUTO,
+            PageSegMode::SingleColumn => TessPageSegMode_PSM_SINGLE_COLUMN,
+            PageSegMode::SingleBlock => TessPageSegMode_PSM_SINGLE_BLOCK,
+            PageSegMode::SingleLine => TessPageSegMode_PSM_SINGLE_LINE,
+            PageSegMode::SingleWord => TessPageSegMode_PSM_SINGLE_WORD,
+            PageSegMode::SparseText => TessPageSegMode_PSM_SPARSE_TEXT,
+            PageSegMode::RawLine => TessPageSegMode_PSM_RAW_LINE,
+        }
+    }
+}
+
+/// Traineddata Tesseract is initialized with, written `eng`, `witcher`, a
+/// Tesseract language code or the path of a traineddata file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Model {
+    /// English model bundled in the binary.
+    Eng,
+    /// Model fine-tuned on notification fonts, read from
+    /// [`crate::WITCHER_MODEL_PATH`].
+    Witcher,
+    /// Tesseract model of a language, e.g. `deu`, read from
+    /// [`crate::TESSDATA_DIR`].
+    Language(String),
+    File(PathBuf),
+}
+
+impl From<&str> for Model {
+    fn from(s: &str) -> Self {
+        match s {
+            "eng" => Model::Eng,
+            "witcher" => Model::Witcher,
+            code if code.chars().all(|char| char.is_ascii_alphanumeric() || char == '_') => {
+                Model::Language(code.to_string())
+            },
+            path => Model::File(PathBuf::from(path)),
+        }
+    }
+}
+
+impl From<String> for Model {
+    fn from(s: String) -> Self {
+        Model::from(s.as_str())
+    }
+}
+
+impl From<Model> for String {
+    fn from(model: Model) -> Self {
+        model.to_string()
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Eng => write!(f, "eng"),
+            Model::Witcher => write!(f, "witcher"),
+            Model::Language(code) => write!(f, "{code}"),
+            Model::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Tesseract settings. Unset values keep Tesseract's defaults.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcrSettings {
+    /// Traineddata to load, the bundled English model if unset.
+    pub model: Option<Model>,
+    pub page_seg_mode: Option<PageSegMode>,
+    /// Characters Tesseract may recognize.
+    pub whitelist: Option<String>,
+    /// Resolution to assume for pictures, in dots per inch.
+    pub dpi: Option<u32>,
+    /// Drop words recognized with a lower confidence, from 0 to 100.
+    pub min_confidence: Option<f32>,
+    /// Any other Tesseract variables.
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Settings in the format they are logged in, e.g. `psm=single_block dpi=300`.
+impl fmt::Display for OcrSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(model) = &self.model {
+            settings.push(format!("model={model}"));
+        }
+        if let Some(mode) = self.page_seg_mode {
+            settings.push(format!("psm={}", mode.as_str()));
+        }
+        if let Some(whitelist) = &self.whitelist {
+            settings.push(format!("whitelist={whitelist:?}"));
+        }
+        if let Some(dpi) = self.dpi {
+            settings.push(format!("dpi={dpi}"));
+        }
+        if let Some(min_confidence) = self.min_confidence {
+            settings.push(format!("min_confidence={min_confidence}"));
+        }
+        for (name, value) in &self.variables {
+            settings.push(format!("{name}={value:?}"));
+        }
+
+        if settings.is_empty() {
+            write!(f, "defaults")
+        } else {
+            write!(f, "{}", settings.join(" "))
+        }
+    }
+}
+
+/// Word recognized by Tesseract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub text: String,
+    /// From 0 to 100.
+    pub confidence: f32,
+    pub bbox: Region,
+    /// Index of the text line the word is on, in Tesseract's reading order.
+    pub line: usize,
+}
+
+/// Line of words, in reading order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub words: Vec<Word>,
+    /// Union of the bounding boxes of the words.
+    pub bbox: Region,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Group words into lines from top to bottom, dropping words below
+/// `min_confidence` and lines left empty.
+pub fn lines(words: &[Word], min_confidence: f32) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    for word in words.iter().filter(|word| word.confidence >= min_confidence) {
+        match lines.last_mut() {
+            Some(line) if line.words[0].line == word.line => {
+                line.bbox = line.bbox.union(&word.bbox);
+                line.words.push(word.clone());
+            },
+            _ => lines.push(Line { words: vec![word.clone()], bbox: word.bbox }),
+        }
+    }
+
+    lines.sort_by_key(|line| line.bbox.y);
+    lines
+}
+
+/// Text of the words at or above `min_confidence`, one line per text line.
+pub fn text(words: &[Word], min_confidence: f32) -> String {
+    lines(words, min_confidence).iter().map(Line::text).collect::<Vec<_>>().join("\n")
+}
+
+// Tesseract trained data.
+const TRAINED_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/eng.traineddata"));
+
+/// RAII wrapper around Tesseract API
+pub struct OcrReader {
+    handle: *mut TessBaseAPI,
+    settings: OcrSettings,
+}
+
+// A Tesseract handle can move to another thread, but `set_variable` changes
+// it through `&self`, so it must not be shared between threads.
+unsafe impl Send for OcrReader {}
+
+impl OcrReader {
+    /// Construct a new instance with the bundled English model and
+    /// Tesseract's default settings.
+    pub fn new() -> Result<Self, Error> {
+        Self::from_bytes("eng", TRAINED_DATA)
+    }
+
+    /// Construct a new instance with a model and Tesseract's default settings.
+    pub fn with_model(model: &Model) -> Result<Self, Error> {
+        match model {
+            Model::Eng => Self::new(),
+            Model::Witcher => Self::from_file(WITCHER_MODEL_PATH),
+            Model::Language(code) => {
+                Self::from_file(Path::new(TESSDATA_DIR).join(format!("{code}.traineddata")))
+            },
+            Model::File(path) => Self::from_file(path),
+        }
+    }
+
+    /// Construct a new instance from a traineddata file, named after its
+    /// language, e.g. `witcher.traineddata`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let language = path.file_stem().unwrap_or_default().to_string_lossy();
+        let data = fs::read(path)
+            .map_err(|_| Error::MissingLanguageData { language: language.to_string() })?;
+
+        Self::from_bytes(&language, &data)
+    }
+
+    /// Construct a new instance from the content of a traineddata file.
+    /// Tesseract keeps its own copy of `data`.
+    pub fn from_bytes(language: &str, data: &[u8]) -> Result<Self, Error> {
+        if data.is_empty() {
+            return Err(Error::MissingLanguageData { language: language.to_string() });
+        }
+        let c_language =
+            CString::new(language).map_err(|_| Error::Init { language: language.to_string() })?;
+
+        // Constructed first so that the handle is deleted if init fails.
+        let reader =
+            Self { handle: unsafe { TessBaseAPICreate() }, settings: OcrSettings::default() };
+        if reader.handle.is_null() {
+            return Err(Error::Init { language: language.to_string() });
+        }
+
+        let status = unsafe {
+            TessBaseAPIInit5(
+                reader.handle,
+                data.as_ptr() as *const i8,
+                data.len() as i32,
+                c_language.as_ptr(),
+                TessOcrEngineMode_OEM_LSTM_ONLY,
+                null_mut(),
+                0,
+                null_mut(),
+                null_mut(),
+                0,
+                1,
+            )
+        };
+        if status != 0 {
+            return Err(Error::Init { language: language.to_string() });
+        }
+
+        Ok(reader)
+    }
+
+    /// Start building an instance with custom settings.
+    pub fn builder() -> OcrReaderBuilder {
+        OcrReaderBuilder::default()
+    }
+
+    /// Settings the instance was built with.
+    pub fn settings(&self) -> &OcrSettings {
+        &self.settings
+    }
+
+    /// Set a Tesseract variable, like `tessedit_char_whitelist`.
+    pub fn set_variable(&self, name: &str, value: &str) -> Result<(), Error> {
+        let error = || Error::Variable { name: name.to_string(), value: value.to_string() };
+        let (c_name, c_value) = match (CString::new(name), CString::new(value)) {
+            (Ok(c_name), Ok(c_value)) => (c_name, c_value),
+            _ => return Err(error()),
+        };
+
+        let ok = unsafe { TessBaseAPISetVariable(self.handle, c_name.as_ptr(), c_value.as_ptr()) };
+        if ok == 0 {
+            return Err(error());
+        }
+
+        Ok(())
+    }
+
+    /// Hand a picture to Tesseract, checking that it has pixels.
+    fn set_image(&self, image: &Picture) -> Result<(), Error> {
+        if image.is_null() || unsafe { pixGetWidth(**image) == 0 || pixGetHeight(**image) == 0 } {
+            return Err(Error::SetImage);
+        }
+        unsafe { TessBaseAPISetImage2(self.handle, image.pix()) };
+
+        Ok(())
+    }
+
+    /// Run OCR on a picture.
+    pub fn get_ocr(&self, image: &Picture) -> Result<String, Error> {
+        self.set_image(image)?;
+
+        let text = unsafe { TessBaseAPIGetUTF8Text(self.handle) };
+        if text.is_null() {
+            return Err(Error::NullResult);
+        }
+        let text_str = unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned();
+
+        unsafe { TessDeleteText(text) };
+
+        Ok(text_str)
+    }
+
+    /// Run OCR on a picture, returning each word with its confidence and
+    /// bounding box.
+    pub fn get_words(&self, image: &Picture) -> Result<Vec<Word>, Error> {
+        self.set_image(image)?;
+        if unsafe { TessBaseAPIRecognize(self.handle, null_mut()) } != 0 {
+            return Err(Error::Recognize);
+        }
+
+        let mut words = Vec::new();
+        unsafe {
+            let iter = TessBaseAPIGetIterator(self.handle);
+            if iter.is_null() {
+                return Ok(words);
+            }
+            let page_iter = TessResultIteratorGetPageIterator(iter);
+            let word_level = TessPageIteratorLevel_RIL_WORD;
+            let mut line = 0;
+
+            loop {
+                let text = TessResultIteratorGetUTF8Text(iter, word_level);
+                if !text.is_null() {
+                    let (mut left, mut top, mut right, mut bottom) = (0, 0, 0, 0);
+                    TessPageIteratorBoundingBox(
+                        page_iter,
+                        word_level,
+                        &mut left,
+                        &mut top,
+                        &mut right,
+                        &mut bottom,
+                    );
+                    words.push(Word {
+                        text: CStr::from_ptr(text).to_string_lossy().into_owned(),
+                        confidence: TessResultIteratorConfidence(iter, word_level),
+                        bbox: Region { x: left, y: top, width: right - left, height: bottom - top },
+                        line,
+                    });
+                    TessDeleteText(text);
+                }
+
+                if TessResultIteratorNext(iter, word_level) == 0 {
+                    break;
+                }
+                // Counted on every word, so that a word without text doesn't
+                // leave the next one on the previous line.
+                if TessPageIteratorIsAtBeginningOf(page_iter, TessPageIteratorLevel_RIL_TEXTLINE)
+                    != 0
+                {
+                    line += 1;
+                }
+            }
+            TessResultIteratorDelete(iter);
+        }
+
+        Ok(words)
+    }
+
+    /// Run OCR on a picture, dropping words below the minimum confidence if
+    /// the settings have one.
+    pub fn get_text(&self, image: &Picture) -> Result<String, Error> {
+        match self.settings.min_confidence {
+            Some(min_confidence) => Ok(text(&self.get_words(image)?, min_confidence)),
+            None => self.get_ocr(image),
+        }
+    }
+}
+
+impl Drop for OcrReader {
+    fn drop(&mut self) {
+        unsafe { TessBaseAPIDelete(self.handle) };
+    }
+}
+
+/// Builder for an [`OcrReader`] with custom Tesseract settings.
+#[derive(Debug, Default, Clone)]
+pub struct OcrReaderBuilder {
+    settings: OcrSettings,
+}
+
+impl From<OcrSettings> for OcrReaderBuilder {
+    fn from(settings: OcrSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl OcrReaderBuilder {
+    pub fn model(mut self, model: Model) -> Self {
+        self.settings.model = Some(model);
+        self
+    }
+
+    pub fn page_seg_mode(mut self, mode: PageSegMode) -> Self {
+        self.settings.page_seg_mode = Some(mode);
+        self
+    }
+
+    pub fn whitelist(mut self, chars: &str) -> Self {
+        self.settings.whitelist = Some(chars.to_string());
+        self
+    }
+
+    /// Set the `user_defined_dpi` hint.
+    pub fn dpi(mut self, dpi: u32) -> Self {
+        self.settings.dpi = Some(dpi);
+        self
+    }
+
+    /// Drop words Tesseract is less confident about, from 0 to 100.
+    pub fn min_confidence(mut self, min_confidence: f32) -> Self {
+        self.settings.min_confidence = Some(min_confidence);
+        self
+    }
+
+    /// Set any Tesseract variable, applied after the other settings.
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.settings.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<OcrReader, Error> {
+        let settings = self.settings;
+        let mut reader = match &settings.model {
+            Some(model) => OcrReader::with_model(model)?,
+            None => OcrReader::new()?,
+        };
+
+        if let Some(mode) = settings.page_seg_mode {
+            unsafe { TessBaseAPISetPageSegMode(reader.handle, mode.to_tess()) };
+        }
+        if let Some(whitelist) = &settings.whitelist {
+            reader.set_variable("tessedit_char_whitelist", whitelist)?;
+        }
+        if let Some(dpi) = settings.dpi {
+            reader.set_variable("user_defined_dpi", &dpi.to_string())?;
+        }
+        for (name, value) in &settings.variables {
+            reader.set_variable(name, value)?;
+        }
+
+        reader.settings = settings;
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TrackerConfig;
+    use crate::data::ActionType;
+    use crate::pipeline::{self, Timings};
+    use crate::synthetic::{render_notification, Variation};
+
+    #[test]
+    fn test_ocr_errors() {
+        let ocr_reader = OcrReader::new().unwrap();
+        let empty = Picture::from(null_mut());
+        assert_eq!(ocr_reader.get_ocr(&empty), Err(Error::SetImage));
+        assert_eq!(ocr_reader.get_words(&empty), Err(Error::SetImage));
+        assert!(matches!(
+            ocr_reader.set_variable("no_such_variable", "1"),
+            Err(Error::Variable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(OcrSettings::default().to_string(), "defaults");
+
+        let builder = OcrReader::builder()
+            .model(Model::Witcher)
+            .page_seg_mode(PageSegMode::SingleBlock)
+            .dpi(300)
+            .variable("textord_min_xheight", "20");
+        assert_eq!(
+            builder.settings.to_string(),
+            "model=witcher psm=single_block dpi=300 textord_min_xheight=\"20\""
+        );
+    }
+
+    #[test]
+    fn test_toml() {
+        let settings: OcrSettings = toml::from_str(
+            "model = \"models/witcher_v2.traineddata\"\npage_seg_mode = \
+             \"single_line\"\nwhitelist = \"abc\"\n[variables]\nload_system_dawg = \"0\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            settings,
+            OcrReader::builder()
+                .model(Model::File(PathBuf::from("models/witcher_v2.traineddata")))
+                .page_seg_mode(PageSegMode::SingleLine)
+                .whitelist("abc")
+                .variable("load_system_dawg", "0")
+                .settings
+        );
+    }
+
+    fn word(text: &str, confidence: f32, line: usize, x: i32, y: i32) -> Word {
+        let bbox = Region { x, y, width: 10 * text.len() as i32, height: 20 };
+        Word { text: text.to_string(), confidence, bbox, line }
+    }
+
+    #[test]
+    fn test_lines() {
+        // Noise recognized as a line of its own above the header.
+        let words = [
+            word("g", 20., 0, 0, 0),
+            word("h", 31., 0, 20, 0),
+            word("quest", 92., 1, 0, 40),
+            word("completed", 90., 1, 60, 42),
+            word("a", 85., 2, 0, 80),
+            word("frying", 88., 2, 20, 80),
+            word("sr", 12., 2, 90, 80),
+            word("pan", 87., 2, 120, 80),
+        ];
+
+        let lines = lines(&words, 50.);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].bbox, Region { x: 0, y: 40, width: 150, height: 22 });
+        assert_eq!(text(&words, 50.), "quest completed\na frying pan");
+        assert_eq!(text(&words, 0.), "g h\nquest completed\na frying sr pan");
+    }
+
+    #[test]
+    fn test_words() {
+        let reader = OcrReader::new().unwrap();
+        let frame = render_notification(
+            ActionType::Quest,
+            "A Frying Pan, Spick and Span",
+            &Variation::random(0),
+        )
+        .unwrap();
+        let config = TrackerConfig::default();
+        let mut timings = Timings::default();
+        let cropped = pipeline::crop(frame, &config, &mut timings).unwrap();
+        let bin_pic = pipeline::binarize(cropped, &config, &mut timings).unwrap();
+
+        // Notifications are rendered in uppercase.
+        let words = reader.get_words(&bin_pic).unwrap();
+        let quest = words.iter().find(|word| word.text.eq_ignore_ascii_case("quest")).unwrap();
+        assert!(quest.confidence > 50.);
+        assert!(quest.bbox.width > 0 && quest.bbox.height > 0);
+        assert!(words
+            .iter()
+            .any(|word| word.text.eq_ignore_ascii_case("frying") && word.line > quest.line));
+    }
+
+    #[test]
+    fn test_build() {
+        let reader = OcrReader::builder().page_seg_mode(PageSegMode::SingleLine).build().unwrap();
+        assert_eq!(reader.settings().page_seg_mode, Some(PageSegMode::SingleLine));
+        assert!(OcrReader::builder().variable("no_such_variable", "1").build().is_err());
+    }
+
+    #[test]
+    fn test_model() {
+        let reader = OcrReader::builder().model(Model::Witcher).build().unwrap();
+        assert_eq!(reader.settings().model, Some(Model::Witcher));
+
+        assert_eq!(Model::from("deu"), Model::Language("deu".to_string()));
+        let missing = OcrReader::builder().model(Model::from("missing.traineddata")).build();
+        assert_eq!(
+            missing.err(),
+            Some(Error::MissingLanguageData { language: "missing".to_string() })
+        );
+    }
+}
@@ -1,14 +1,18 @@
 //! Functions for loading and preprocessing pictures.
 
 use std::ffi::CString;
-use std::ops::{Deref, Range};
+use std::ops::Deref;
+use std::path::Path;
 use std::ptr::null_mut;
-use std::slice;
+use std::{fs, slice};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use leptonica_sys::*;
 
-use crate::{CROP_RANGE, HSV_RANGE};
+use crate::config::ComponentFilter;
+use crate::error::Error;
+use crate::roi::Roi;
+use crate::{Crop, HsvRange};
 
 /// RAII picture.
 pub struct Picture {
@@ -49,37 +53,51 @@ impl Picture {
         data
     }
 
-    /// Crop the center-leftmost part.
-    pub fn into_cropped(self) -> Result<Self> {
+    /// Write as PNG to a file.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(fs::write(path, self.to_vec())?)
+    }
+
+    /// Crop the notification region of a HUD at `hud_scale`, and scale it to
+    /// the target glyph height.
+    pub fn into_cropped(self, crop: &Crop, hud_scale: f32) -> Result<Self, Error> {
         let pix = unsafe {
-            let width = pixGetWidth(self.pix) as f32;
-            let height = pixGetHeight(self.pix) as f32;
+            let width = pixGetWidth(self.pix);
+            let height = pixGetHeight(self.pix);
 
-            if width == 0. || height == 0. {
-                return Err(anyhow!("Width and height are {width} {height}, can't crop"));
+            if width == 0 || height == 0 {
+                return Err(Error::EmptyFrame { width, height });
             }
 
-            let left = width * CROP_RANGE.left;
-            let top = height * CROP_RANGE.top;
-            let right = width * (1. - CROP_RANGE.right);
-            let bottom = height * (1. - CROP_RANGE.bottom);
+            let roi = Roi::new(width, height, crop, hud_scale);
 
-            let x = left as i32;
-            let y = top as i32;
-            let width = (right - left) as i32;
-            let height = (bottom - top) as i32;
-
-            let mut boxx = boxCreate(x, y, width, height);
+            let mut boxx = boxCreate(roi.x, roi.y, roi.width, roi.height);
             let pix = Picture::from(pixClipRectangle(self.pix, boxx, null_mut()));
             boxDestroy(&mut boxx);
 
-            pixScale(pix.pix, 2.0, 2.0)
+            if pix.is_null() {
+                return Err(Error::Crop);
+            }
+            Self::from(pixScale(pix.pix, roi.scale, roi.scale))
         };
 
-        Ok(Self::from(pix))
+        if pix.is_null() {
+            Err(Error::Crop)
+        } else {
+            Ok(pix)
+        }
+    }
+}
+
+impl Clone for Picture {
+    fn clone(&self) -> Self {
+        Picture::from(unsafe { pixCopy(null_mut(), self.pix) })
     }
 }
 
+// Pictures own their Pix, which Leptonica doesn't tie to a thread.
+unsafe impl Send for Picture {}
+
 impl Deref for Picture {
     type Target = *mut Pix;
 
@@ -102,40 +120,102 @@ impl Drop for Picture {
     }
 }
 
-/// Process picture to obtain something that's easy to extract OCR from.
+/// Convert an RGB pixel to HSV the way `pixConvertRGBToHSV` does, with hue in
+/// `0..240` and saturation and value in `0..256`.
+#[inline]
+fn rgb_to_hsv(r: i32, g: i32, b: i32) -> (u8, u8, u8) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    if delta == 0 {
+        return (0, 0, max as u8);
+    }
+
+    let s = (255. * delta as f64 / max as f64 + 0.5) as i32;
+    let mut h = if r == max {
+        (g - b) as f32 / delta as f32
+    } else if g == max {
+        2. + (b - r) as f32 / delta as f32
+    } else {
+        4. + (r - g) as f32 / delta as f32
+    };
+    h *= 40.;
+    if h < 0. {
+        h += 240.;
+    }
+    if h >= 239.5 {
+        h = 0.;
+    }
+
+    ((h + 0.5) as u8, s as u8, max as u8)
+}
+
+/// Check whether a 32 bpp Leptonica pixel falls within `hsv`.
+#[inline]
+fn is_in_hsv_range(pixel: u32, hsv: &HsvRange) -> bool {
+    let (r, g, b) = ((pixel >> 24) as i32, (pixel >> 16 & 0xff) as i32, (pixel >> 8 & 0xff) as i32);
+
+    // Most pixels are too dark, which is cheap to rule out.
+    if !hsv.value.contains(&(r.max(g).max(b) as u8)) {
+        return false;
+    }
+
+    let (h, s, v) = rgb_to_hsv(r, g, b);
+    hsv.hue.contains(&h) && hsv.saturation.contains(&s) && hsv.value.contains(&v)
+}
+
+/// Binarize a picture, keeping only pixels within `hsv`.
+///
+/// Reads and writes the Leptonica data buffers directly, building each 32
+/// pixel word of the mask at once.
 ///
 /// # Safety
 ///
-/// haha
-pub unsafe fn preprocess(picture: Picture) -> Result<Picture> {
-    const HRANGE: Range<u8> = HSV_RANGE.0;
-    const SRANGE: Range<u8> = HSV_RANGE.1;
-    const VRANGE: Range<u8> = HSV_RANGE.2;
-
-    // Discard pixels outside of a narrow HSV range.
-    let hsv_pic = Picture::from(pixConvertRGBToHSV(null_mut(), *picture));
-    let bin_pic = Picture::from(pixCreate(pixGetWidth(*picture), pixGetHeight(*picture), 1));
-
-    // Loop through all pixels.
-    for y in 0..pixGetHeight(*picture) {
-        for x in 0..pixGetWidth(*picture) {
-            // Extract HSV point at (x, y).
-            let (mut h, mut s, mut v) = (0, 0, 0);
-            pixGetRGBPixel(*hsv_pic, x, y, &mut h, &mut s, &mut v);
-            let (h, s, v) = (h as u8, s as u8, v as u8);
-
-            let is_in_range = HRANGE.contains(&h) && SRANGE.contains(&s) && VRANGE.contains(&v);
-            if is_in_range {
-                pixSetPixel(*bin_pic, x, y, 1);
-            }
+/// `picture` must point to a valid Leptonica Pix.
+pub unsafe fn color_mask(picture: &Picture, hsv: &HsvRange) -> Result<Picture, Error> {
+    let rgb_pic = Picture::from(pixConvertTo32(**picture));
+    if rgb_pic.is_null() {
+        return Err(Error::ColorMask);
+    }
+
+    let width = pixGetWidth(*rgb_pic) as usize;
+    let height = pixGetHeight(*rgb_pic) as usize;
+    let bin_pic = Picture::from(pixCreate(width as i32, height as i32, 1));
+
+    let src_wpl = pixGetWpl(*rgb_pic) as usize;
+    let dst_wpl = pixGetWpl(*bin_pic) as usize;
+    let src = slice::from_raw_parts(pixGetData(*rgb_pic), src_wpl * height);
+    let dst = slice::from_raw_parts_mut(pixGetData(*bin_pic), dst_wpl * height);
+
+    for (src_row, dst_row) in src.chunks_exact(src_wpl).zip(dst.chunks_exact_mut(dst_wpl)) {
+        for (word, pixels) in dst_row.iter_mut().zip(src_row[..width].chunks(32)) {
+            // The leftmost pixel is the most significant bit.
+            *word = pixels.iter().enumerate().fold(0, |word, (i, &pixel)| {
+                word | (is_in_hsv_range(pixel, hsv) as u32) << (31 - i)
+            });
         }
     }
 
-    pixDilateBrick(*bin_pic, *bin_pic, 3, 3);
-    pixErodeBrick(*bin_pic, *bin_pic, 3, 3);
+    Ok(bin_pic)
+}
 
-    // Connected component analysis
-    let connected_comps = pixConnComp(*bin_pic, null_mut(), 4);
+/// Close small gaps in the glyphs of a binary picture.
+///
+/// # Safety
+///
+/// `bin_pic` must point to a valid 1 bpp Leptonica Pix.
+pub unsafe fn close_gaps(bin_pic: &Picture) {
+    pixDilateBrick(**bin_pic, **bin_pic, 3, 3);
+    pixErodeBrick(**bin_pic, **bin_pic, 3, 3);
+}
+
+/// Clear connected components that are unlikely to be glyphs.
+///
+/// # Safety
+///
+/// `bin_pic` must point to a valid 1 bpp Leptonica Pix.
+pub unsafe fn filter_components(bin_pic: &Picture, filter: &ComponentFilter) {
+    let connected_comps = pixConnComp(**bin_pic, null_mut(), 4);
     for i in 0..boxaGetCount(connected_comps) {
         let bbox = boxaGetBox(connected_comps, i, L_CLONE as _);
         let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
@@ -144,16 +224,13 @@ pub unsafe fn preprocess(picture: Picture) -> Result<Picture> {
         let area = w * h;
         let aspect_ratio = w as f32 / h as f32;
 
-        if !(100..30000).contains(&area) && !(0.0..1.0).contains(&aspect_ratio)
+        if !filter.area.contains(&area) && !filter.aspect_ratio.contains(&aspect_ratio)
         // && !(10..30).contains(&w)
         // && !(20..40).contains(&h)
         {
-            pixRasterop(*bin_pic, x, y, w, h, PIX_CLR as _, *bin_pic, x, y);
+            pixRasterop(**bin_pic, x, y, w, h, PIX_CLR as _, **bin_pic, x, y);
         }
     }
-    pixInvert(*bin_pic, *bin_pic);
-
-    Ok(bin_pic)
 }
 
 #[cfg(test)]
@@ -161,9 +238,13 @@ mod tests {
     use std::ffi::CString;
     use std::fs;
     use std::path::Path;
+    use std::time::Instant;
 
     use super::*;
-    use crate::TEST_CASES;
+    use crate::compare::load_fixtures;
+    use crate::config::TrackerConfig;
+    use crate::pipeline::{self, Timings};
+    use crate::{GROUND_TRUTH_PATH, HSV_RANGE};
 
     fn preprocess_and_save<P: AsRef<Path>>(path: P) {
         let path = path.as_ref();
@@ -178,15 +259,62 @@ mod tests {
         let dest_path = CString::new(dest_path.to_str().unwrap()).unwrap();
 
         let data = fs::read(path).unwrap();
-        let cropped = Picture::from_mem(data).into_cropped().unwrap();
-        let pic = unsafe { preprocess(cropped).unwrap() };
+        let mut timings = Timings::default();
+        let config = TrackerConfig::default();
+        let cropped = pipeline::crop(Picture::from_mem(data), &config, &mut timings).unwrap();
+        let pic = pipeline::binarize(cropped, &config, &mut timings).unwrap();
         unsafe { pixWritePng(dest_path.as_ptr(), pic.pix, 0.) };
     }
 
+    /// Per-pixel implementation of [`color_mask`], to compare against.
+    unsafe fn color_mask_reference(picture: &Picture) -> Picture {
+        let HsvRange { hue, saturation, value } = HSV_RANGE;
+
+        let hsv_pic = Picture::from(pixConvertRGBToHSV(null_mut(), **picture));
+        let bin_pic = Picture::from(pixCreate(pixGetWidth(**picture), pixGetHeight(**picture), 1));
+
+        for y in 0..pixGetHeight(**picture) {
+            for x in 0..pixGetWidth(**picture) {
+                let (mut h, mut s, mut v) = (0, 0, 0);
+                pixGetRGBPixel(*hsv_pic, x, y, &mut h, &mut s, &mut v);
+                let (h, s, v) = (h as u8, s as u8, v as u8);
+
+                if hue.contains(&h) && saturation.contains(&s) && value.contains(&v) {
+                    pixSetPixel(*bin_pic, x, y, 1);
+                }
+            }
+        }
+
+        bin_pic
+    }
+
+    #[test]
+    fn test_color_mask() {
+        for fixture in load_fixtures(GROUND_TRUTH_PATH).unwrap() {
+            let test_case = &fixture.path;
+            let picture = Picture::from_mem(fs::read(test_case).unwrap());
+            let picture = Picture::from(unsafe { pixConvertTo32(*picture) });
+
+            let start = Instant::now();
+            let reference = unsafe { color_mask_reference(&picture) };
+            let elapsed_reference = start.elapsed();
+
+            let start = Instant::now();
+            let mask = unsafe { color_mask(&picture, &HSV_RANGE).unwrap() };
+            let elapsed = start.elapsed();
+
+            println!("{test_case}: {elapsed_reference:?} -> {elapsed:?}");
+
+            let mut same = 0;
+            unsafe { pixEqual(*reference, *mask, &mut same) };
+            assert_eq!(same, 1, "{test_case}");
+        }
+    }
+
     #[test]
     fn test_preprocess() {
-        for test_case in TEST_CASES {
-            preprocess_and_save(test_case);
+        for fixture in load_fixtures(GROUND_TRUTH_PATH).unwrap() {
+            preprocess_and_save(&fixture.path);
         }
     }
 }
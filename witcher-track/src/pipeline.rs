@@ -0,0 +1,338 @@
+//! OCR pipeline shared by the live loop, the replays and the tests.
+//!
+//! Frames go through capture, ROI crop, color mask, notification box
+//! localization, gate, morphology, component filter and OCR, in that order, and
+//! each stage is timed. Parsing the text is timed as a final stage by the
+//! caller.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use leptonica_sys::{pixInvert, pixOr};
+
+use crate::cache::OcrCache;
+use crate::config::{ComponentFilter, TextField, TrackerConfig};
+use crate::gate::FrameGate;
+use crate::locate::{self, Region};
+use crate::picture::{close_gaps, color_mask, filter_components, Picture};
+use crate::{HsvRange, OcrReader};
+
+/// Stage of the pipeline, in execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Capture,
+    Crop,
+    ColorMask,
+    Locate,
+    Gate,
+    Morphology,
+    Components,
+    Ocr,
+    Parse,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::Capture,
+        Stage::Crop,
+        Stage::ColorMask,
+        Stage::Locate,
+        Stage::Gate,
+        Stage::Morphology,
+        Stage::Components,
+        Stage::Ocr,
+        Stage::Parse,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Capture => "capture",
+            Stage::Crop => "crop",
+            Stage::ColorMask => "color mask",
+            Stage::Locate => "locate",
+            Stage::Gate => "gate",
+            Stage::Morphology => "morphology",
+            Stage::Components => "components",
+            Stage::Ocr => "ocr",
+            Stage::Parse => "parse",
+        }
+    }
+}
+
+/// Time spent in each stage while processing one frame.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings([Duration; Stage::ALL.len()]);
+
+impl Timings {
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.0[stage as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.0.iter().sum()
+    }
+
+    /// Add time spent outside of [`run`] to `stage`.
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        self.0[stage as usize] += duration;
+    }
+
+    /// Run `f`, adding its duration to `stage`.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.0[stage as usize] += start.elapsed();
+        result
+    }
+}
+
+/// Crop a captured frame to the region notifications appear in.
+pub fn crop(screenshot: Picture, config: &TrackerConfig, timings: &mut Timings) -> Result<Picture> {
+    Ok(timings.time(Stage::Crop, || screenshot.into_cropped(&config.crop, config.hud_scale))?)
+}
+
+/// Turn a cropped frame into black text on a white background, cropped to the
+/// notification box as [`run`] reads it when no fields are configured.
+pub fn binarize(
+    cropped: Picture,
+    config: &TrackerConfig,
+    timings: &mut Timings,
+) -> Result<Picture> {
+    let (_, bin_pic) = locate_box(&cropped, config, timings)?;
+    clean(&bin_pic, &config.components, timings);
+
+    Ok(bin_pic)
+}
+
+/// Discard pixels outside of a narrow HSV range.
+fn mask(cropped: &Picture, hsv: &HsvRange, timings: &mut Timings) -> Result<Picture> {
+    Ok(timings.time(Stage::ColorMask, || unsafe { color_mask(cropped, hsv) })?)
+}
+
+/// Union of the color masks of `fields`, which must not be empty.
+fn fields_mask(cropped: &Picture, fields: &[TextField], timings: &mut Timings) -> Result<Picture> {
+    let union = mask(cropped, &fields[0].hsv, timings)?;
+    for field in &fields[1..] {
+        let field_mask = mask(cropped, &field.hsv, timings)?;
+        timings.time(Stage::ColorMask, || unsafe { pixOr(*union, *union, *field_mask) });
+    }
+
+    Ok(union)
+}
+
+/// Remove noise from a color mask, leaving black text on a white background.
+fn clean(bin_pic: &Picture, filter: &ComponentFilter, timings: &mut Timings) {
+    timings.time(Stage::Morphology, || unsafe { close_gaps(bin_pic) });
+    timings.time(Stage::Components, || unsafe {
+        filter_components(bin_pic, filter);
+        pixInvert(**bin_pic, **bin_pic);
+    });
+}
+
+/// Extract text from a binarized frame.
+pub fn ocr(ocr_reader: &OcrReader, bin_pic: &Picture, timings: &mut Timings) -> Result<String> {
+    Ok(timings.time(Stage::Ocr, || ocr_reader.get_text(bin_pic))?)
+}
+
+/// Mask a cropped frame and find the notification box in it.
+///
+/// With text fields configured, the mask is the union of the field masks, so
+/// that the box and the gate cover every field. Returns the region of the box
+/// in the cropped frame, and its color mask.
+pub fn locate_box(
+    cropped: &Picture,
+    config: &TrackerConfig,
+    timings: &mut Timings,
+) -> Result<(Region, Picture)> {
+    let bin_pic = match config.fields.as_slice() {
+        [] => mask(cropped, &config.hsv, timings)?,
+        fields => fields_mask(cropped, fields, timings)?,
+    };
+    let region = timings.time(Stage::Locate, || locate::box_region(&bin_pic, &config.locate));
+    let bin_pic = timings.time(Stage::Locate, || locate::clip(&bin_pic, region))?;
+
+    Ok((region, bin_pic))
+}
+
+/// Read the text of the notification box found by [`locate_box`], looking OCR
+/// results up in `cache` first.
+///
+/// With text fields configured, each field of the notification box is masked
+/// and read on its own, and their text is joined line by line in order.
+pub fn read_box(
+    ocr_reader: &OcrReader,
+    config: &TrackerConfig,
+    cache: &OcrCache,
+    cropped: &Picture,
+    region: Region,
+    bin_pic: Picture,
+    timings: &mut Timings,
+) -> Result<String> {
+    let global_whitelist = ocr_reader.settings().whitelist.as_deref().unwrap_or_default();
+    if config.fields.is_empty() {
+        clean(&bin_pic, &config.components, timings);
+        return timings.time(Stage::Ocr, || {
+            cache.get_or_try_insert(&bin_pic, global_whitelist, || {
+                Ok(ocr_reader.get_text(&bin_pic)?)
+            })
+        });
+    }
+
+    let mut read_fields = || -> Result<Vec<String>> {
+        let mut lines = Vec::with_capacity(config.fields.len());
+        for field in &config.fields {
+            let field_pic =
+                timings.time(Stage::Crop, || locate::clip(cropped, region.rows(&field.rows)))?;
+            let bin_pic = mask(&field_pic, &field.hsv, timings)?;
+            clean(&bin_pic, &config.components, timings);
+
+            let whitelist = match field.whitelist.as_str() {
+                "" => global_whitelist,
+                whitelist => whitelist,
+            };
+            lines.push(timings.time(Stage::Ocr, || {
+                cache.get_or_try_insert(&bin_pic, whitelist, || {
+                    ocr_reader.set_variable("tessedit_char_whitelist", whitelist)?;
+                    Ok(ocr_reader.get_text(&bin_pic)?)
+                })
+            })?);
+        }
+        Ok(lines)
+    };
+
+    // Field whitelists are set on the reader, which outlives the frame.
+    let lines = read_fields();
+    ocr_reader.set_variable("tessedit_char_whitelist", global_whitelist)?;
+
+    Ok(lines?.join("\n"))
+}
+
+/// Capture a frame and run it through every stage, unless `gate` skips OCR.
+/// OCR results are looked up in `cache` first.
+pub fn run(
+    ocr_reader: &OcrReader,
+    config: &TrackerConfig,
+    gate: &FrameGate,
+    cache: &OcrCache,
+    capture: impl FnOnce() -> Result<Picture>,
+) -> Result<(String, Timings)> {
+    let mut timings = Timings::default();
+
+    let screenshot = timings.time(Stage::Capture, capture)?;
+    let cropped = crop(screenshot, config, &mut timings)?;
+    let (region, bin_pic) = locate_box(&cropped, config, &mut timings)?;
+    if let Some(text) = timings.time(Stage::Gate, || gate.check(&bin_pic)) {
+        return Ok((text, timings));
+    }
+
+    // Cleaning the mask for OCR changes it, so the gate gets a copy.
+    let text =
+        read_box(ocr_reader, config, cache, &cropped, region, bin_pic.clone(), &mut timings)?;
+    gate.record(&bin_pic, &text);
+
+    Ok((text, timings))
+}
+
+/// Run a single screenshot through the same stages as [`run`], without a gate
+/// or cache.
+pub fn read_frame(
+    ocr_reader: &OcrReader,
+    config: &TrackerConfig,
+    screenshot: Picture,
+) -> Result<(String, Timings)> {
+    let mut timings = Timings::default();
+
+    let cropped = crop(screenshot, config, &mut timings)?;
+    let (region, bin_pic) = locate_box(&cropped, config, &mut timings)?;
+    let cache = OcrCache::new(0);
+    let text = read_box(ocr_reader, config, &cache, &cropped, region, bin_pic, &mut timings)?;
+
+    Ok((text, timings))
+}
+
+/// Nearest-rank percentile `p` of durations sorted in ascending order.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{parse_action, slugify, Action, ActionType};
+    use crate::error::is_recoverable;
+    use crate::synthetic::{render_notification, Variation};
+    use crate::HSV_RANGE;
+
+    #[test]
+    fn test_fields_mask() {
+        // The whole-box colors match nothing, so the box is only found through
+        // the fields.
+        let field = |name: &str, rows| TextField {
+            name: name.to_string(),
+            rows,
+            hsv: HSV_RANGE,
+            whitelist: String::new(),
+        };
+        let config = TrackerConfig {
+            hsv: HsvRange { hue: 200..201, saturation: 0..1, value: 0..1 },
+            fields: vec![field("header", 0.0..0.4), field("name", 0.4..1.0)],
+            ..Default::default()
+        };
+        let ocr_reader = OcrReader::new().unwrap();
+        let gate = FrameGate::new(config.gate);
+        let cache = OcrCache::new(config.ocr_cache_size);
+        let variation = Variation { noise: 0., clutter: 0, ..Default::default() };
+
+        // Two notifications under the same header must not be gated as one.
+        for name in ["Precious Cargo", "Ugly Baby"] {
+            let frame = render_notification(ActionType::Quest, name, &variation).unwrap();
+            let (text, _) = run(&ocr_reader, &config, &gate, &cache, || Ok(frame)).unwrap();
+            assert_eq!(parse_action(&text), Some(Action::new(ActionType::Quest, slugify(name))));
+        }
+        assert_eq!(gate.stats().unchanged, 0);
+    }
+
+    #[test]
+    fn test_frame_errors() {
+        let ocr_reader = OcrReader::new().unwrap();
+        let read = |config: &TrackerConfig, frame| {
+            let gate = FrameGate::new(config.gate);
+            let cache = OcrCache::new(config.ocr_cache_size);
+            run(&ocr_reader, config, &gate, &cache, || Ok(frame)).unwrap_err()
+        };
+
+        // A frame without pixels is skipped, not fatal.
+        let error = read(&TrackerConfig::default(), Picture::from(std::ptr::null_mut()));
+        assert!(is_recoverable(&error), "{error:#}");
+
+        // So is a field band too thin to hold a row of pixels.
+        let config = TrackerConfig {
+            fields: vec![TextField {
+                name: "header".to_string(),
+                rows: 0.0..0.001,
+                hsv: HSV_RANGE,
+                whitelist: String::new(),
+            }],
+            ..Default::default()
+        };
+        let variation = Variation { noise: 0., clutter: 0, ..Default::default() };
+        let frame = render_notification(ActionType::Quest, "Precious Cargo", &variation).unwrap();
+        let error = read(&config, frame);
+        assert!(is_recoverable(&error), "{error:#}");
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&sorted, 0.), Duration::from_millis(1));
+        assert_eq!(percentile(&sorted, 50.), Duration::from_millis(5));
+        assert_eq!(percentile(&sorted, 95.), Duration::from_millis(10));
+        assert_eq!(percentile(&sorted, 100.), Duration::from_millis(10));
+        assert_eq!(percentile(&[], 50.), Duration::ZERO);
+    }
+}
@@ -0,0 +1,204 @@
+//! End-to-end accuracy report over a replay session.
+
+use std::fmt;
+
+use crate::data::{slugify, Action, ActionType};
+use crate::tuning::Label;
+
+/// Action flagged by the tracker on a replay frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub frame: usize,
+    pub action: Action,
+}
+
+/// Extract detections from the `(message, content)` log entries of a session.
+///
+/// Flagging happens before the `FRAME` entry of the frame it was parsed from,
+/// so detections are attributed to the next `FRAME` entry.
+pub fn detections(logs: &[(String, String)]) -> Vec<Detection> {
+    let mut detections = Vec::new();
+    let mut pending = Vec::new();
+
+    for (message, content) in logs {
+        if message == "FRAME" {
+            if let Ok(frame) = content.parse() {
+                detections.extend(pending.drain(..).map(|action| Detection { frame, action }));
+            }
+            continue;
+        }
+
+        let kind = ["FOUND ", "DUPLICATE ", "UNKNOWN "]
+            .iter()
+            .find_map(|outcome| message.strip_prefix(outcome));
+        if let Some(Ok(action_type)) = kind.map(|kind| kind.to_lowercase().parse::<ActionType>()) {
+            pending.push(Action::new(action_type, slugify(content)));
+        }
+    }
+
+    detections
+}
+
+/// Expected action that was detected, and how many frames it took.
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub action: Action,
+    pub frame: usize,
+    pub latency: usize,
+}
+
+/// Comparison of a session's detections against the expected actions.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub hits: Vec<Hit>,
+    pub misses: Vec<Label>,
+    pub false_positives: Vec<Detection>,
+    /// Per-frame processing time in seconds.
+    pub timings: Vec<f64>,
+}
+
+impl Report {
+    /// Match each detection with the earliest unmatched label of the same
+    /// action shown on its frame, or up to `tolerance` frames before it.
+    pub fn new(
+        labels: Vec<Label>,
+        detections: Vec<Detection>,
+        tolerance: usize,
+        timings: Vec<f64>,
+    ) -> Self {
+        let mut unmatched = labels;
+        unmatched.sort_by_key(|label| *label.frames.start());
+
+        let mut report = Self { timings, ..Self::default() };
+        for detection in detections {
+            let position = unmatched.iter().position(|label| {
+                label.action == detection.action
+                    && *label.frames.start() <= detection.frame
+                    && detection.frame <= label.frames.end() + tolerance
+            });
+            match position {
+                Some(position) => {
+                    let label = unmatched.remove(position);
+                    report.hits.push(Hit {
+                        latency: detection.frame - label.frames.start(),
+                        frame: detection.frame,
+                        action: detection.action,
+                    });
+                },
+                None => report.false_positives.push(detection),
+            }
+        }
+        report.misses = unmatched;
+
+        report
+    }
+
+    pub fn true_positives(&self) -> usize {
+        self.hits.len()
+    }
+
+    pub fn mean_latency(&self) -> Option<f64> {
+        mean(self.hits.iter().map(|hit| hit.latency as f64))
+    }
+
+    pub fn max_latency(&self) -> Option<usize> {
+        self.hits.iter().map(|hit| hit.latency).max()
+    }
+}
+
+fn mean(values: impl ExactSizeIterator<Item = f64>) -> Option<f64> {
+    let len = values.len();
+    (len > 0).then(|| values.sum::<f64>() / len as f64)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for hit in &self.hits {
+            writeln!(f, "HIT    {:>6} +{:<3} {:?}", hit.frame, hit.latency, hit.action)?;
+        }
+        for label in &self.misses {
+            writeln!(f, "MISS   {:>6}      {:?}", label.frames.start(), label.action)?;
+        }
+        for detection in &self.false_positives {
+            writeln!(f, "FALSE  {:>6}      {:?}", detection.frame, detection.action)?;
+        }
+
+        writeln!(f, "True positives:  {}", self.true_positives())?;
+        writeln!(f, "Misses:          {}", self.misses.len())?;
+        writeln!(f, "False positives: {}", self.false_positives.len())?;
+        if let (Some(mean), Some(max)) = (self.mean_latency(), self.max_latency()) {
+            writeln!(f, "Latency:         {mean:.1} frames mean, {max} max")?;
+        }
+        if let Some(mean_timing) = mean(self.timings.iter().copied()) {
+            let max_timing = self.timings.iter().copied().fold(0., f64::max);
+            writeln!(
+                f,
+                "Frame time:      {:.1}ms mean, {:.1}ms max",
+                1000. * mean_timing,
+                1000. * max_timing
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuning::parse_labels;
+
+    fn log(message: &str, content: &str) -> (String, String) {
+        (message.to_string(), content.to_string())
+    }
+
+    #[test]
+    fn test_report() {
+        let logs = vec![
+            log("LOG", "Started test run"),
+            log("FRAME", "120"),
+            log("FOUND QUEST", "precious cargo"),
+            log("FRAME", "123"),
+            log("DUPLICATE DIAGRAM", "feline boots"),
+            log("FRAME", "200"),
+        ];
+        let detections = detections(&logs);
+        assert_eq!(detections, vec![
+            Detection { frame: 123, action: Action::Quest("precious cargo".to_string()) },
+            Detection { frame: 200, action: Action::Diagram("feline boots".to_string()) },
+        ]);
+
+        let labels = parse_labels(
+            "120 150 quest Precious Cargo\n300 320 formula Torn-out page: Earth elemental \
+             decoction\n",
+        )
+        .unwrap();
+        let report = Report::new(labels, detections, 5, vec![0.1, 0.3]);
+
+        assert_eq!(report.hits, vec![Hit {
+            action: Action::Quest("precious cargo".to_string()),
+            frame: 123,
+            latency: 3
+        }]);
+        assert_eq!(report.misses.len(), 1);
+        assert_eq!(report.false_positives.len(), 1);
+        assert_eq!(report.max_latency(), Some(3));
+    }
+
+    #[test]
+    fn test_report_tolerance() {
+        let labels = || parse_labels("120 150 quest Precious Cargo\n").unwrap();
+        let detection =
+            |frame| Detection { frame, action: Action::Quest("precious cargo".to_string()) };
+
+        let late = Report::new(labels(), vec![detection(155)], 5, vec![]);
+        assert_eq!(late.hits.len(), 1);
+        assert_eq!(late.max_latency(), Some(35));
+
+        let stale = Report::new(labels(), vec![detection(156)], 5, vec![]);
+        assert_eq!((stale.hits.len(), stale.misses.len(), stale.false_positives.len()), (0, 1, 1));
+
+        let early = Report::new(labels(), vec![detection(119)], 5, vec![]);
+        assert_eq!(early.false_positives.len(), 1);
+    }
+}
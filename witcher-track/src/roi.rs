@@ -0,0 +1,72 @@
+//! Notification region for a given frame size and HUD scale.
+
+use crate::{Crop, GLYPH_HEIGHT, HUD_ASPECT, TARGET_GLYPH_HEIGHT};
+
+/// Region of a frame to crop, in pixels, and the factor to upscale it by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Roi {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub scale: f32,
+}
+
+impl Roi {
+    /// Compute the region notifications appear in.
+    ///
+    /// The HUD is laid out on a `HUD_ASPECT` area fitted to the frame height,
+    /// anchored to the left edge, so ultrawide frames only add space on the
+    /// right. Narrower frames are letterboxed. `crop` gives the region at
+    /// HUD scale 1; larger HUD scales grow it from its left edge and vertical
+    /// center. The scale brings glyphs to `TARGET_GLYPH_HEIGHT` pixels.
+    pub fn new(frame_width: i32, frame_height: i32, crop: &Crop, hud_scale: f32) -> Self {
+        let (frame_width, frame_height) = (frame_width as f32, frame_height as f32);
+
+        let hud_width = frame_width.min(frame_height * HUD_ASPECT);
+        let hud_height = hud_width / HUD_ASPECT;
+        let hud_top = (frame_height - hud_height) / 2.;
+
+        let left = hud_width * crop.left;
+        let right = hud_width * (1. - crop.right);
+        let top = hud_top + hud_height * crop.top;
+        let bottom = hud_top + hud_height * (1. - crop.bottom);
+
+        let center = (top + bottom) / 2.;
+        let half_height = (bottom - top) / 2. * hud_scale;
+        let right = (left + (right - left) * hud_scale).min(frame_width);
+        let top = (center - half_height).max(0.);
+        let bottom = (center + half_height).min(frame_height);
+
+        Self {
+            x: left as i32,
+            y: top as i32,
+            width: (right - left) as i32,
+            height: (bottom - top) as i32,
+            scale: TARGET_GLYPH_HEIGHT / (GLYPH_HEIGHT * hud_height * hud_scale),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CROP_RANGE;
+
+    #[test]
+    fn test_roi() {
+        let full_hd = Roi::new(1920, 1080, &CROP_RANGE, 1.);
+        assert_eq!(full_hd, Roi { x: 19, y: 486, width: 940, height: 216, scale: 2. });
+
+        // Ultrawide frames keep the same region, anchored to the left.
+        assert_eq!(Roi::new(2560, 1080, &CROP_RANGE, 1.), full_hd);
+
+        // Higher resolutions crop more pixels but scale them less.
+        let uhd = Roi::new(3840, 2160, &CROP_RANGE, 1.);
+        assert_eq!((uhd.width, uhd.height, uhd.scale), (1881, 432, 1.));
+
+        let large_hud = Roi::new(1920, 1080, &CROP_RANGE, 1.5);
+        assert_eq!((large_hud.x, large_hud.height), (19, 324));
+        assert!(large_hud.scale < full_hd.scale);
+    }
+}
@@ -9,7 +9,7 @@ use windows::w;
 use windows::Win32::Foundation::RECT;
 use windows::Win32::UI::WindowsAndMessaging::{FindWindowW, GetClientRect, GetWindowRect};
 
-use crate::Picture;
+use crate::picture::Picture;
 
 /// Capture the entire Witcher's window.
 pub fn capture() -> Result<Picture> {
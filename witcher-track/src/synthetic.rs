@@ -0,0 +1,377 @@
+//! Synthetic notification frames for end-to-end OCR testing.
+//!
+//! Text is rendered with Leptonica's built-in bitmap fonts, so no font file is
+//! needed.
+
+use std::ffi::CString;
+use std::ptr::{null, null_mut};
+
+use anyhow::{anyhow, Result};
+use leptonica_sys::*;
+
+use crate::data::ActionType;
+use crate::picture::Picture;
+
+/// Color of notification text, inside `HSV_RANGE`.
+const TEXT_COLOR: (i32, i32, i32) = (233, 213, 180);
+/// Size of the bitmap font glyphs, before scaling.
+const FONT_SIZE: i32 = 20;
+/// Left edge of the notification, as a fraction of the frame width.
+const LEFT: f32 = 0.07;
+/// Right edge past which names wrap, as a fraction of the frame width.
+const RIGHT: f32 = 0.48;
+/// Top of the header, as a fraction of the frame height.
+const TOP: f32 = 0.5;
+/// Resolutions picked from by [`Variation::random`].
+const RESOLUTIONS: &[(i32, i32)] = &[(1280, 720), (1920, 1080), (2560, 1440)];
+
+/// Rendering variations applied to a synthetic frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Variation {
+    /// Frame width and height in pixels.
+    pub resolution: (i32, i32),
+    /// Height of the item name glyphs, as a fraction of the frame height.
+    pub text_height: f32,
+    /// Fraction of pixels replaced by random colors.
+    pub noise: f32,
+    /// Number of random rectangles drawn behind the text.
+    pub clutter: usize,
+    /// Whether [`render_lines`] paints the text in uppercase rather than in
+    /// its original case. Notifications are always uppercase.
+    pub uppercase: bool,
+    /// Seed of the random generator.
+    pub seed: u64,
+}
+
+impl Default for Variation {
+    fn default() -> Self {
+        Self {
+            resolution: (1920, 1080),
+            text_height: 0.035,
+            noise: 0.01,
+            clutter: 20,
+            uppercase: false,
+            seed: 0,
+        }
+    }
+}
+
+impl Variation {
+    /// Derive a variation from a seed, sweeping resolution, scale, noise and
+    /// clutter. One in four variations is uppercase.
+    pub fn random(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        Self {
+            resolution: RESOLUTIONS[rng.below(RESOLUTIONS.len() as u64) as usize],
+            text_height: 0.025 + 0.02 * rng.unit(),
+            noise: 0.02 * rng.unit(),
+            clutter: rng.below(40) as usize,
+            uppercase: rng.below(4) == 0,
+            seed,
+        }
+    }
+}
+
+/// Render a full frame showing the notification for a catalog entry.
+pub fn render_notification(
+    action_type: ActionType,
+    name: &str,
+    variation: &Variation,
+) -> Result<Picture> {
+    let mut rng = Rng::new(variation.seed);
+    let frame = background(variation, &mut rng)?;
+    let (width, height) = variation.resolution;
+
+    let name_height = (height as f32 * variation.text_height) as i32;
+    let header_height = name_height * 2 / 3;
+    let left = (width as f32 * LEFT) as i32;
+    let max_width = (width as f32 * (RIGHT - LEFT)) as i32;
+    let mut y = (height as f32 * TOP) as i32;
+
+    paint_text(&frame, &action_type.header().to_uppercase(), left, y, header_height)?;
+    y += header_height * 3 / 2;
+
+    for line in wrap(&name.to_uppercase(), name_height, max_width)? {
+        paint_text(&frame, &line, left, y, name_height)?;
+        y += name_height * 5 / 4;
+    }
+
+    add_noise(&frame, variation, &mut rng);
+    Ok(frame)
+}
+
+/// Render each line of a notification text, wrapped as in game, on a frame of
+/// its own. Glyphs are `variation.text_height` high, and the text keeps its
+/// case unless `variation.uppercase` is set.
+///
+/// Returns the text of each line, as painted, with its frame.
+pub fn render_lines(text: &str, variation: &Variation) -> Result<Vec<(String, Picture)>> {
+    let (width, height) = variation.resolution;
+    let glyph_height = (height as f32 * variation.text_height) as i32;
+    let left = (width as f32 * LEFT) as i32;
+    let max_width = (width as f32 * (RIGHT - LEFT)) as i32;
+    let y = (height as f32 * TOP) as i32;
+
+    let text = if variation.uppercase { text.to_uppercase() } else { text.to_string() };
+    wrap(&text, glyph_height, max_width)?
+        .into_iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let mut rng = Rng::new(variation.seed.wrapping_add(idx as u64));
+            let frame = background(variation, &mut rng)?;
+            paint_text(&frame, &line, left, y, glyph_height)?;
+            add_noise(&frame, variation, &mut rng);
+            Ok((line, frame))
+        })
+        .collect()
+}
+
+/// Create a frame filled with a dark color and random rectangles.
+fn background(variation: &Variation, rng: &mut Rng) -> Result<Picture> {
+    let (width, height) = variation.resolution;
+
+    let frame = Picture::from(unsafe { pixCreate(width, height, 32) });
+    if frame.is_null() {
+        return Err(anyhow!("Could not create a {width}x{height} frame"));
+    }
+
+    unsafe {
+        pixSetAllArbitrary(
+            *frame,
+            rgb(rng.below(60) as i32, rng.below(60) as i32, rng.below(60) as i32),
+        );
+
+        for _ in 0..variation.clutter {
+            let x = rng.below(width as u64) as i32;
+            let y = rng.below(height as u64) as i32;
+            let w = rng.below(width as u64 / 4) as i32 + 1;
+            let h = rng.below(height as u64 / 4) as i32 + 1;
+            let color = rgb(rng.below(256) as i32, rng.below(256) as i32, rng.below(256) as i32);
+
+            let mut boxx = boxCreate(x, y, w, h);
+            pixSetInRectArbitrary(*frame, boxx, color);
+            boxDestroy(&mut boxx);
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Replace a fraction of the pixels of a frame with random colors.
+fn add_noise(frame: &Picture, variation: &Variation, rng: &mut Rng) {
+    let (width, height) = variation.resolution;
+    let count = (variation.noise * (width * height) as f32) as usize;
+
+    for _ in 0..count {
+        let x = rng.below(width as u64) as i32;
+        let y = rng.below(height as u64) as i32;
+        let (r, g, b) = (rng.below(256) as i32, rng.below(256) as i32, rng.below(256) as i32);
+        unsafe { pixSetRGBPixel(**frame, x, y, r, g, b) };
+    }
+}
+
+/// Split text into lines no wider than `max_width` at the given glyph height.
+fn wrap(text: &str, glyph_height: i32, max_width: i32) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+        if !line.is_empty() && text_width(&candidate, glyph_height)? > max_width {
+            lines.push(line);
+            line = word.to_string();
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+
+    Ok(lines)
+}
+
+/// Width in pixels of a line of text at the given glyph height.
+fn text_width(text: &str, glyph_height: i32) -> Result<i32> {
+    let c_text = CString::new(text)?;
+    let bmf = Bmf::new()?;
+    let mut width = 0;
+    if unsafe { bmfGetStringWidth(bmf.0, c_text.as_ptr(), &mut width) } != 0 {
+        return Err(anyhow!("Could not measure {text:?}"));
+    }
+
+    Ok((width as f32 * bmf.scale(glyph_height)) as i32)
+}
+
+/// Paint a line of text with its top-left corner at `(x, y)`.
+fn paint_text(frame: &Picture, text: &str, x: i32, y: i32, glyph_height: i32) -> Result<()> {
+    let c_text = CString::new(text)?;
+    let bmf = Bmf::new()?;
+
+    unsafe {
+        let (mut width, mut baseline) = (0, 0);
+        if bmfGetStringWidth(bmf.0, c_text.as_ptr(), &mut width) != 0
+            || bmfGetBaseline(bmf.0, b'A' as _, &mut baseline) != 0
+        {
+            return Err(anyhow!("Could not measure {text:?}"));
+        }
+
+        let mask = Picture::from(pixCreate(width + 2, (*bmf.0).lineheight + 2, 1));
+        if mask.is_null() {
+            return Err(anyhow!("Could not create a mask for {text:?}"));
+        }
+        let status = pixSetTextline(
+            *mask,
+            bmf.0,
+            c_text.as_ptr(),
+            1,
+            1,
+            baseline + 1,
+            null_mut(),
+            null_mut(),
+        );
+        if status != 0 {
+            return Err(anyhow!("Could not render {text:?}"));
+        }
+
+        let scale = bmf.scale(glyph_height);
+        let mask = Picture::from(pixScale(*mask, scale, scale));
+        if mask.is_null() {
+            return Err(anyhow!("Could not scale {text:?} by {scale}"));
+        }
+        let color = rgb(TEXT_COLOR.0, TEXT_COLOR.1, TEXT_COLOR.2);
+        if pixPaintThroughMask(**frame, *mask, x, y, color) != 0 {
+            return Err(anyhow!("Could not paint {text:?}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Compose a 32 bpp Leptonica pixel.
+fn rgb(r: i32, g: i32, b: i32) -> u32 {
+    let mut pixel = 0;
+    unsafe { composeRGBPixel(r, g, b, &mut pixel) };
+    pixel
+}
+
+/// RAII wrapper around a Leptonica bitmap font.
+struct Bmf(*mut L_BMF);
+
+impl Bmf {
+    fn new() -> Result<Self> {
+        let bmf = unsafe { bmfCreate(null(), FONT_SIZE) };
+        if bmf.is_null() {
+            return Err(anyhow!("Could not create bitmap font of size {FONT_SIZE}"));
+        }
+        Ok(Self(bmf))
+    }
+
+    /// Scale factor to render glyphs at the given height.
+    fn scale(&self, glyph_height: i32) -> f32 {
+        glyph_height as f32 / unsafe { (*self.0).lineheight } as f32
+    }
+}
+
+impl Drop for Bmf {
+    fn drop(&mut self) {
+        unsafe { bmfDestroy(&mut self.0) };
+    }
+}
+
+/// Xorshift generator, so that frames are reproducible from their seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random integer in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    /// Random float in `0.0..1.0`.
+    fn unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::OcrCache;
+    use crate::config::TrackerConfig;
+    use crate::data::{parse_action, slugify, Action};
+    use crate::gate::FrameGate;
+    use crate::{pipeline, OcrReader};
+
+    /// Minimum fraction of synthetic frames that must parse correctly.
+    const MIN_ACCURACY: f64 = 0.8;
+    /// Every how many catalog entries one is rendered by [`test_synthetic`].
+    const SAMPLE_STEP: usize = 25;
+
+    #[test]
+    fn test_synthetic() {
+        check_synthetic(SAMPLE_STEP);
+    }
+
+    #[test]
+    #[ignore = "OCRs the whole catalog, run with --ignored"]
+    fn test_synthetic_catalog() {
+        check_synthetic(1);
+    }
+
+    #[test]
+    fn test_render_lines_case() {
+        let lines = |uppercase| {
+            let variation = Variation { uppercase, ..Default::default() };
+            let lines = render_lines("Twisted Firestarter", &variation).unwrap();
+            lines.into_iter().map(|(line, _)| line).collect::<Vec<_>>().join(" ")
+        };
+
+        assert_eq!(lines(false), "Twisted Firestarter");
+        assert_eq!(lines(true), "TWISTED FIRESTARTER");
+    }
+
+    /// Render every `step`th catalog entry and check how many parse back.
+    fn check_synthetic(step: usize) {
+        let ocr_reader = OcrReader::new().unwrap();
+        let config = TrackerConfig::default();
+        let mut total = (0, 0);
+
+        for action_type in ActionType::ALL {
+            let mut correct = (0, 0);
+            for (idx, entry) in action_type.catalog_entries().enumerate().step_by(step) {
+                let frame = render_notification(action_type, entry, &Variation::random(idx as u64))
+                    .unwrap();
+                let gate = FrameGate::new(config.gate);
+                let cache = OcrCache::new(config.ocr_cache_size);
+                let (text, _) =
+                    pipeline::run(&ocr_reader, &config, &gate, &cache, || Ok(frame)).unwrap();
+                let parsed = parse_action(text);
+
+                let expected = Action::new(action_type, slugify(entry));
+                if parsed.as_ref() != Some(&expected) {
+                    println!("{entry:?} -> {parsed:?}");
+                } else {
+                    correct.0 += 1;
+                }
+                correct.1 += 1;
+            }
+
+            println!("{}: {}/{}", action_type.as_str(), correct.0, correct.1);
+            total = (total.0 + correct.0, total.1 + correct.1);
+        }
+
+        let accuracy = total.0 as f64 / total.1 as f64;
+        println!("total: {}/{} ({:.1}%)", total.0, total.1, 100. * accuracy);
+        assert!(accuracy >= MIN_ACCURACY);
+    }
+}
@@ -0,0 +1,113 @@
+//! Ground truth for fine-tuning the Tesseract model with tesstrain.
+//!
+//! Each sample line is rendered on synthetic frames, which go through the
+//! same crop and binarization as live frames, so that the model is trained on
+//! what it reads at runtime. Lines are written as `<name>.png` and
+//! `<name>.gt.txt` pairs, the layout tesstrain reads from its ground truth
+//! directory.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::config::TrackerConfig;
+use crate::data::ActionType;
+use crate::pipeline::{self, Timings};
+use crate::synthetic::{render_lines, Variation};
+
+/// Height of header glyphs relative to item name glyphs.
+const HEADER_SCALE: f32 = 2. / 3.;
+
+/// Kind of text a sample line holds, which sets its glyph height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleKind {
+    Header,
+    Name,
+    /// Single word of a header or name, at name height.
+    Word,
+}
+
+impl SampleKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SampleKind::Header => "header",
+            SampleKind::Name => "name",
+            SampleKind::Word => "word",
+        }
+    }
+}
+
+/// Notification headers, catalog item names and each distinct word in them.
+pub fn samples() -> Vec<(SampleKind, String)> {
+    let headers: BTreeSet<_> = ActionType::ALL.iter().map(|t| t.header().to_string()).collect();
+    let names: BTreeSet<_> =
+        ActionType::ALL.iter().flat_map(|t| t.catalog_entries()).map(str::to_string).collect();
+    let words: BTreeSet<_> = headers
+        .iter()
+        .chain(&names)
+        .flat_map(|text| text.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+
+    let headers = headers.into_iter().map(|text| (SampleKind::Header, text));
+    let names = names.into_iter().map(|text| (SampleKind::Name, text));
+    let words = words.into_iter().map(|text| (SampleKind::Word, text));
+    headers.chain(names).chain(words).collect()
+}
+
+/// Render every sample `variations` times into `dir`, and return the number of
+/// lines written.
+pub fn write_ground_truth<P: AsRef<Path>>(
+    dir: P,
+    config: &TrackerConfig,
+    variations: u64,
+) -> Result<usize> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {dir:?}"))?;
+
+    let mut count = 0;
+    for (idx, (kind, text)) in samples().into_iter().enumerate() {
+        for seed in 0..variations {
+            let mut variation = Variation::random(idx as u64 * variations + seed);
+            if kind == SampleKind::Header {
+                variation.text_height *= HEADER_SCALE;
+            }
+
+            for (line_idx, (line, frame)) in
+                render_lines(&text, &variation)?.into_iter().enumerate()
+            {
+                let mut timings = Timings::default();
+                let cropped = pipeline::crop(frame, config, &mut timings)?;
+                let bin_pic = pipeline::binarize(cropped, config, &mut timings)?;
+
+                let name = format!("{}{idx:04}_{seed}_{line_idx}", kind.as_str());
+                bin_pic.save_png(dir.join(format!("{name}.png")))?;
+                fs::write(dir.join(format!("{name}.gt.txt")), line)?;
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        let samples = samples();
+        let of_kind = |kind| samples.iter().filter(|(k, _)| *k == kind).collect::<Vec<_>>();
+
+        assert_eq!(of_kind(SampleKind::Header).len(), ActionType::ALL.len());
+        assert!(of_kind(SampleKind::Name).iter().any(|(_, text)| text == "Twisted Firestarter"));
+
+        let words = of_kind(SampleKind::Word);
+        assert!(words.iter().any(|(_, text)| text == "completed"));
+        assert!(words.iter().any(|(_, text)| text == "Firestarter"));
+        assert!(words.iter().all(|(_, text)| !text.contains(' ')));
+        assert_eq!(words.iter().filter(|(_, text)| text == "Diagram:").count(), 1);
+    }
+}
@@ -0,0 +1,205 @@
+//! Threshold tuning over a labeled replay.
+
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::data::{
+    parse_action_scored, slugify, Action, ActionType, Language, Threshold, Thresholds,
+};
+
+/// Header thresholds tried by [`sweep`].
+const HEADER_STEPS: RangeInclusive<u32> = 8..=18;
+/// Name thresholds tried by [`sweep`].
+const NAME_STEPS: RangeInclusive<u32> = 10..=19;
+/// Increment between two consecutive threshold steps.
+const STEP: f64 = 0.05;
+
+/// Action expected on a range of replay frames.
+#[derive(Debug, PartialEq)]
+pub struct Label {
+    pub frames: RangeInclusive<usize>,
+    pub action: Action,
+}
+
+/// Parse a labels file.
+///
+/// Each line reads `<first frame> <last frame> <quest|formula|diagram> <name>`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_labels(s: &str) -> Result<Vec<Label>> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(idx, line)| {
+            parse_label(line).with_context(|| format!("Invalid label on line {}", idx + 1))
+        })
+        .collect()
+}
+
+fn parse_label(line: &str) -> Result<Label> {
+    let mut fields = line.trim().splitn(4, char::is_whitespace);
+    let mut next = || fields.next().ok_or_else(|| anyhow!("Missing field in {line:?}"));
+
+    let first: usize = next()?.parse()?;
+    let last: usize = next()?.parse()?;
+    let action_type: ActionType = next()?.parse()?;
+    let name = slugify(next()?);
+
+    Ok(Label { frames: first..=last, action: Action::new(action_type, name) })
+}
+
+/// Return the action expected on a frame.
+fn expected(labels: &[Label], frame: usize) -> Option<&Action> {
+    labels.iter().find(|label| label.frames.contains(&frame)).map(|label| &label.action)
+}
+
+/// Per-frame detection counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+}
+
+impl Score {
+    pub fn precision(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0. {
+            0.
+        } else {
+            2. * precision * recall / (precision + recall)
+        }
+    }
+
+    fn add(&mut self, expected: Option<&Action>, detected: Option<&Action>) {
+        match (expected, detected) {
+            (Some(expected), Some(detected)) if expected == detected => self.true_positives += 1,
+            (expected, detected) => {
+                self.false_negatives += expected.is_some() as usize;
+                self.false_positives += detected.is_some() as usize;
+            },
+        }
+    }
+}
+
+fn ratio(num: usize, den: usize) -> f64 {
+    if den == 0 {
+        1.0
+    } else {
+        num as f64 / den as f64
+    }
+}
+
+/// Score OCR text of replay frames against the labels, counting only actions
+/// of the given type.
+pub fn score(
+    frames: &[(usize, String)],
+    labels: &[Label],
+    thresholds: &Thresholds,
+    language: &Language,
+    action_type: ActionType,
+) -> Score {
+    let mut score = Score::default();
+    for (frame, text) in frames {
+        let expected = expected(labels, *frame).filter(|a| a.action_type() == action_type);
+        let detected = parse_action_scored(text, thresholds, language)
+            .map(|(action, _)| action)
+            .filter(|a| a.action_type() == action_type);
+        score.add(expected, detected.as_ref());
+    }
+    score
+}
+
+/// Try every combination of header and name thresholds for one action type,
+/// leaving the other types at their `base` thresholds.
+pub fn sweep(
+    frames: &[(usize, String)],
+    labels: &[Label],
+    base: &Thresholds,
+    language: &Language,
+    action_type: ActionType,
+) -> Vec<(Threshold, Score)> {
+    let mut results = Vec::new();
+
+    for header_step in HEADER_STEPS {
+        let mut thresholds = *base;
+        // Parse once with no name threshold, then filter by score: the name
+        // threshold only decides whether the best match is kept.
+        *thresholds.get_mut(action_type) =
+            Threshold { header: header_step as f64 * STEP, name: 0. };
+
+        let detections: Vec<_> = frames
+            .iter()
+            .map(|(frame, text)| {
+                let detected = parse_action_scored(text, &thresholds, language)
+                    .filter(|(action, _)| action.action_type() == action_type);
+                let expected = expected(labels, *frame).filter(|a| a.action_type() == action_type);
+                (expected, detected)
+            })
+            .collect();
+
+        for name_step in NAME_STEPS {
+            let threshold =
+                Threshold { header: header_step as f64 * STEP, name: name_step as f64 * STEP };
+            let mut score = Score::default();
+            for (expected, detected) in &detections {
+                let detected = detected
+                    .as_ref()
+                    .filter(|(_, similarity)| *similarity >= threshold.name)
+                    .map(|(action, _)| action);
+                score.add(*expected, detected);
+            }
+            results.push((threshold, score));
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::THRESHOLDS;
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels(
+            "# replay labels\n\n120 150 quest Precious Cargo\n300 320 formula Torn-out page: \
+             Earth elemental decoction\n",
+        )
+        .unwrap();
+
+        assert_eq!(labels, vec![
+            Label { frames: 120..=150, action: Action::Quest("precious cargo".to_string()) },
+            Label {
+                frames: 300..=320,
+                action: Action::Formula("torn out page earth elemental decoction".to_string())
+            },
+        ]);
+        assert!(parse_labels("120 quest Precious Cargo").is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let labels = parse_labels("1 2 quest Precious Cargo").unwrap();
+        let frames = vec![
+            (0, "".to_string()),
+            (1, "quest completed\nprecious cargo".to_string()),
+            (2, "".to_string()),
+            (3, "quest completed\ndeserter gold".to_string()),
+        ];
+
+        let score = score(&frames, &labels, &THRESHOLDS, Language::english(), ActionType::Quest);
+        assert_eq!(score, Score { true_positives: 1, false_positives: 1, false_negatives: 1 });
+        assert_eq!(score.precision(), 0.5);
+        assert_eq!(score.recall(), 0.5);
+    }
+}
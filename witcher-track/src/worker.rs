@@ -0,0 +1,350 @@
+//! OCR workers running the pipeline on captured frames.
+//!
+//! Tesseract handles can't be used from several threads at once, so each
+//! worker owns its `OcrReader`. Workers share the gate and cache, so that
+//! frames dealt round-robin are still compared against the last one read.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::cache::{CacheStats, OcrCache};
+use crate::config::TrackerConfig;
+use crate::error::Error;
+use crate::gate::{FrameGate, GateStats};
+use crate::ocr::OcrReaderBuilder;
+use crate::picture::Picture;
+use crate::pipeline::{self, Stage, Timings};
+use crate::OcrReader;
+
+/// OCR state owned by a single thread.
+pub struct Worker {
+    ocr_reader: OcrReader,
+    config: TrackerConfig,
+    gate: Arc<FrameGate>,
+    cache: Arc<OcrCache>,
+}
+
+impl Worker {
+    /// Construct a new instance, with its own gate and cache.
+    pub fn new(config: &TrackerConfig) -> Result<Self> {
+        let gate = Arc::new(FrameGate::new(config.gate));
+        Self::sharing(config, gate, Arc::new(OcrCache::new(config.ocr_cache_size)))
+    }
+
+    /// Construct a new instance using the gate and cache of other workers.
+    fn sharing(config: &TrackerConfig, gate: Arc<FrameGate>, cache: Arc<OcrCache>) -> Result<Self> {
+        Ok(Self {
+            ocr_reader: OcrReaderBuilder::from(config.ocr_settings()).build()?,
+            config: config.clone(),
+            gate,
+            cache,
+        })
+    }
+
+    /// Capture a frame and extract its text.
+    pub fn process(&self, capture: impl FnOnce() -> Result<Picture>) -> Result<(String, Timings)> {
+        pipeline::run(&self.ocr_reader, &self.config, &self.gate, &self.cache, capture)
+    }
+
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats { gate: self.gate.stats(), cache: self.cache.stats() }
+    }
+}
+
+/// Gate and cache counters of the workers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerStats {
+    pub gate: GateStats,
+    pub cache: CacheStats,
+}
+
+/// Result of processing a frame, or `None` if it was dropped from the queue.
+pub type FrameResult = Option<Result<(String, Timings)>>;
+
+/// Message from the queue or a worker about a frame.
+struct Output {
+    frame: usize,
+    result: FrameResult,
+}
+
+/// Captured frame waiting for a worker.
+struct Job {
+    frame: usize,
+    picture: Picture,
+    capture_time: Duration,
+}
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    next_frame: usize,
+    dropped: usize,
+    /// Taken when the queue is closed, so that the pool sees the end of the
+    /// outputs once the workers are done.
+    outputs: Option<Sender<Output>>,
+}
+
+/// Bounded queue of captured frames that drops the oldest frame when full,
+/// since a stale frame is worth less than a fresh one.
+pub struct FrameQueue {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+impl FrameQueue {
+    fn new(capacity: usize, outputs: Sender<Output>) -> Self {
+        let state = QueueState {
+            jobs: VecDeque::with_capacity(capacity),
+            next_frame: 0,
+            dropped: 0,
+            outputs: Some(outputs),
+        };
+        Self { capacity, state: Mutex::new(state), available: Condvar::new() }
+    }
+
+    /// Queue a captured frame, numbering it after the previous one.
+    ///
+    /// Returns `false` once the queue is closed.
+    pub fn push(&self, picture: Picture, capture_time: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(outputs) = state.outputs.clone() else {
+            return false;
+        };
+
+        if state.jobs.len() >= self.capacity.max(1) {
+            if let Some(stale) = state.jobs.pop_front() {
+                state.dropped += 1;
+                let _ = outputs.send(Output { frame: stale.frame, result: None });
+            }
+        }
+
+        let frame = state.next_frame;
+        state.next_frame += 1;
+        state.jobs.push_back(Job { frame, picture, capture_time });
+        self.available.notify_one();
+
+        true
+    }
+
+    /// Stop accepting frames. Workers finish the queued ones, then exit.
+    pub fn close(&self) {
+        self.state.lock().unwrap().outputs = None;
+        self.available.notify_all();
+    }
+
+    /// Number of frames dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.state.lock().unwrap().dropped
+    }
+
+    /// Wait for the next frame, or `None` once the queue is closed and empty.
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                return Some(job);
+            }
+            state.outputs.as_ref()?;
+            state = self.available.wait(state).unwrap();
+        }
+    }
+}
+
+/// Process queued frames until the queue is closed, sending their results.
+///
+/// A panic on a frame is sent as its error, so that the pool doesn't wait for
+/// that frame forever.
+fn work(
+    queue: &FrameQueue,
+    tx: &Sender<Output>,
+    mut process: impl FnMut(Picture) -> Result<(String, Timings)>,
+) {
+    while let Some(job) = queue.pop() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| process(job.picture)))
+            .unwrap_or_else(|_| Err(Error::Panicked.into()))
+            .map(|(text, mut timings)| {
+                timings.add(Stage::Capture, job.capture_time);
+                (text, timings)
+            });
+        if tx.send(Output { frame: job.frame, result: Some(result) }).is_err() {
+            break;
+        }
+    }
+}
+
+/// Pool of workers fed by a [`FrameQueue`], yielding results in frame order.
+pub struct WorkerPool {
+    queue: Arc<FrameQueue>,
+    outputs: Receiver<Output>,
+    pending: BTreeMap<usize, FrameResult>,
+    next_frame: usize,
+    gate: Arc<FrameGate>,
+    cache: Arc<OcrCache>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Start the configured number of workers, reading from a queue of
+    /// `config.frame_queue_size` frames.
+    pub fn new(config: &TrackerConfig) -> Result<Self> {
+        let (tx, outputs) = mpsc::channel();
+        let mut pool = Self::idle(config, tx.clone(), outputs);
+
+        for _ in 0..config.workers {
+            let worker = Worker::sharing(config, pool.gate.clone(), pool.cache.clone())?;
+            let (queue, tx) = (pool.queue.clone(), tx.clone());
+            pool.workers.push(thread::spawn(move || {
+                work(&queue, &tx, |picture| worker.process(|| Ok(picture)))
+            }));
+        }
+
+        Ok(pool)
+    }
+
+    /// Construct a pool without workers.
+    fn idle(config: &TrackerConfig, tx: Sender<Output>, outputs: Receiver<Output>) -> Self {
+        Self {
+            queue: Arc::new(FrameQueue::new(config.frame_queue_size, tx)),
+            outputs,
+            pending: BTreeMap::new(),
+            next_frame: 0,
+            gate: Arc::new(FrameGate::new(config.gate)),
+            cache: Arc::new(OcrCache::new(config.ocr_cache_size)),
+            workers: Vec::new(),
+        }
+    }
+
+    /// Queue to push captured frames into.
+    pub fn queue(&self) -> Arc<FrameQueue> {
+        self.queue.clone()
+    }
+
+    /// Counters of the gate and cache shared by the workers.
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats { gate: self.gate.stats(), cache: self.cache.stats() }
+    }
+}
+
+impl Iterator for WorkerPool {
+    type Item = (usize, FrameResult);
+
+    /// Wait for the result of the next frame, in capture order.
+    ///
+    /// Returns `None` once the queue is closed and every frame was returned.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next_frame) {
+                let frame = self.next_frame;
+                self.next_frame += 1;
+                return Some((frame, result));
+            }
+
+            match self.outputs.recv() {
+                Ok(output) => {
+                    self.pending.insert(output.frame, output.result);
+                },
+                // Only frames lost along with a worker can be missing here.
+                Err(_) => {
+                    let (&frame, _) = self.pending.iter().next()?;
+                    self.next_frame = frame;
+                },
+            }
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.queue.close();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use leptonica_sys::pixCreate;
+
+    use super::*;
+
+    #[test]
+    fn test_queue_drops_oldest() {
+        let (tx, rx) = mpsc::channel();
+        let queue = FrameQueue::new(2, tx);
+        for _ in 0..3 {
+            assert!(queue.push(Picture::from(unsafe { pixCreate(1, 1, 32) }), Duration::ZERO));
+        }
+
+        let dropped = rx.try_recv().unwrap();
+        assert_eq!(dropped.frame, 0);
+        assert!(dropped.result.is_none());
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop().unwrap().frame, 1);
+
+        queue.close();
+        assert!(!queue.push(Picture::from(unsafe { pixCreate(1, 1, 32) }), Duration::ZERO));
+        assert_eq!(queue.pop().unwrap().frame, 2);
+        assert!(queue.pop().is_none());
+    }
+
+    fn output(frame: usize, text: &str) -> Output {
+        Output { frame, result: Some(Ok((text.to_string(), Timings::default()))) }
+    }
+
+    fn text(item: Option<(usize, FrameResult)>) -> Option<(usize, String)> {
+        item.map(|(frame, result)| (frame, result.unwrap().unwrap().0))
+    }
+
+    #[test]
+    fn test_pool_order() {
+        let (tx, rx) = mpsc::channel();
+        let mut pool = WorkerPool::idle(&TrackerConfig::default(), tx.clone(), rx);
+
+        for frame in [2, 0, 1] {
+            tx.send(output(frame, &frame.to_string())).unwrap();
+        }
+        assert_eq!(text(pool.next()), Some((0, "0".to_string())));
+        assert_eq!(text(pool.next()), Some((1, "1".to_string())));
+        assert_eq!(text(pool.next()), Some((2, "2".to_string())));
+
+        // Frame 3 is lost along with its worker, so frame 4 follows once the
+        // outputs end.
+        tx.send(output(4, "4")).unwrap();
+        drop(tx);
+        pool.queue.close();
+        assert_eq!(text(pool.next()), Some((4, "4".to_string())));
+        assert!(pool.next().is_none());
+    }
+
+    #[test]
+    fn test_pool_panic() {
+        let (tx, rx) = mpsc::channel();
+        let mut pool = WorkerPool::idle(&TrackerConfig::default(), tx.clone(), rx);
+        for _ in 0..3 {
+            pool.queue.push(Picture::from(unsafe { pixCreate(1, 1, 32) }), Duration::ZERO);
+        }
+        pool.queue.close();
+
+        let mut frame = 0;
+        work(&pool.queue, &tx, |_| {
+            frame += 1;
+            assert_ne!(frame, 2, "OCR failed");
+            Ok((frame.to_string(), Timings::default()))
+        });
+        drop(tx);
+
+        assert_eq!(text(pool.next()), Some((0, "1".to_string())));
+        let (frame, result) = pool.next().unwrap();
+        let error = result.unwrap().unwrap_err();
+        assert_eq!((frame, error.downcast_ref()), (1, Some(&Error::Panicked)));
+        assert_eq!(text(pool.next()), Some((2, "3".to_string())));
+        assert!(pool.next().is_none());
+    }
+}